@@ -1,25 +1,80 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error;
 use std::io::{self, BufRead};
 use std::num::ParseIntError;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WinRule {
+    // A complete row or column.
+    Lines,
+    // A complete row, column, or diagonal of a square board.
+    LinesAndDiagonals,
+    // All numbers on the board.
+    FullCard,
+    // The numbers in the four corners.
+    FourCorners,
+}
+
+impl std::str::FromStr for WinRule {
+    type Err = UnknownWinRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(WinRule::Lines),
+            "diagonals" => Ok(WinRule::LinesAndDiagonals),
+            "full-card" => Ok(WinRule::FullCard),
+            "corners" => Ok(WinRule::FourCorners),
+            _ => Err(UnknownWinRuleError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownWinRuleError(String);
+
+impl std::fmt::Display for UnknownWinRuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Unknown win rule '{}', expected lines, diagonals, full-card, or corners.",
+            self.0
+        )
+    }
+}
+
+impl Error for UnknownWinRuleError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BingoBoard {
-    marked_in_col: [u8; 5],
-    marked_in_row: [u8; 5],
-    number_positions: HashMap<u8, (usize, usize)>,
+    win_rule: WinRule,
+    num_rows: usize,
+    num_cols: usize,
+    marked_in_col: Vec<usize>,
+    marked_in_row: Vec<usize>,
+    marked_in_diagonal: [usize; 2],
+    marked_corners: usize,
+    number_positions: HashMap<u32, (usize, usize)>,
 }
 
 impl BingoBoard {
-    fn new(board: &[[u8; 5]; 5]) -> Self {
+    fn new<R: AsRef<[u32]>>(board: &[R]) -> Self {
+        let num_rows = board.len();
+        let num_cols = board.first().map_or(0, |row| row.as_ref().len());
         Self {
-            marked_in_col: [0; 5],
-            marked_in_row: [0; 5],
+            win_rule: WinRule::Lines,
+            num_rows,
+            num_cols,
+            marked_in_col: vec![0; num_cols],
+            marked_in_row: vec![0; num_rows],
+            marked_in_diagonal: [0; 2],
+            marked_corners: 0,
             number_positions: board
                 .iter()
                 .enumerate()
                 .flat_map(|(i, row)| {
-                    row.iter()
+                    row.as_ref()
+                        .iter()
                         .enumerate()
                         .map(move |(j, number)| (*number, (i, j)))
                 })
@@ -27,51 +82,148 @@ impl BingoBoard {
         }
     }
 
+    fn with_win_rule(mut self, win_rule: WinRule) -> Self {
+        self.win_rule = win_rule;
+        self
+    }
+
+    fn is_square(&self) -> bool {
+        self.num_rows == self.num_cols
+    }
+
+    fn corners(&self) -> HashSet<(usize, usize)> {
+        let (last_row, last_col) = (
+            self.num_rows.saturating_sub(1),
+            self.num_cols.saturating_sub(1),
+        );
+        [(0, 0), (0, last_col), (last_row, 0), (last_row, last_col)]
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    fn has_complete_line(&self) -> bool {
+        self.marked_in_row.iter().any(|&x| x >= self.num_cols)
+            || self.marked_in_col.iter().any(|&x| x >= self.num_rows)
+    }
+
     fn has_won(&self) -> bool {
-        self.marked_in_row.iter().any(|&x| x >= 5) || self.marked_in_col.iter().any(|&x| x >= 5)
+        match self.win_rule {
+            WinRule::Lines => self.has_complete_line(),
+            WinRule::LinesAndDiagonals => {
+                self.has_complete_line()
+                    || (self.is_square()
+                        && self.marked_in_diagonal.iter().any(|&x| x >= self.num_rows))
+            }
+            WinRule::FullCard => self.number_positions.is_empty(),
+            WinRule::FourCorners => self.marked_corners >= self.corners().len(),
+        }
     }
 
-    fn mark(&mut self, number: u8) {
+    fn mark(&mut self, number: u32) {
         if let Some((_, (i, j))) = self.number_positions.remove_entry(&number) {
             self.marked_in_col[j] += 1;
             self.marked_in_row[i] += 1;
+            if self.is_square() {
+                if i == j {
+                    self.marked_in_diagonal[0] += 1;
+                }
+                if i + j + 1 == self.num_cols {
+                    self.marked_in_diagonal[1] += 1;
+                }
+            }
+            if self.corners().contains(&(i, j)) {
+                self.marked_corners += 1;
+            }
         }
     }
 
+    // The index of the draw completing the board, if any.
+    fn completion_draw(&self, numbers: &[u32]) -> Option<usize> {
+        let mut board = self.clone();
+        numbers.iter().position(|&number| {
+            board.mark(number);
+            board.has_won()
+        })
+    }
+
     fn score(&self) -> u64 {
         self.number_positions.keys().map(|&x| x as u64).sum()
     }
+
+    fn marked(&self) -> Vec<Vec<bool>> {
+        let mut marked = vec![vec![true; self.num_cols]; self.num_rows];
+        for &(i, j) in self.number_positions.values() {
+            marked[i][j] = false;
+        }
+        marked
+    }
+}
+
+#[derive(Debug)]
+struct InconsistentBoardError {
+    row_length: usize,
+    expected: usize,
+}
+
+impl std::fmt::Display for InconsistentBoardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Bingo board row with {} numbers, expected {}.",
+            self.row_length, self.expected
+        )
+    }
 }
 
+impl Error for InconsistentBoardError {}
+
+// Reads the rows of a board up to the next empty line. The board size is given
+// by the number of rows and the numbers in the first row.
 fn parse_bingo_board<R: BufRead>(reader: &mut R) -> Result<BingoBoard, Box<dyn Error>> {
-    let mut board = [[0; 5]; 5];
-    for i in 0..5 {
-        let mut buf = String::with_capacity(3 * 5);
-        while buf.trim().is_empty() {
-            buf.clear();
-            let num_read = reader.read_line(&mut buf)?;
-            if num_read <= 0 {
-                return Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::UnexpectedEof,
-                    "Premature end of bingo board.",
-                )));
+    let mut board: Vec<Vec<u32>> = Vec::new();
+    loop {
+        let mut buf = String::new();
+        let num_read = reader.read_line(&mut buf)?;
+        if buf.trim().is_empty() {
+            if num_read == 0 || !board.is_empty() {
+                break;
             }
+            continue;
         }
-        for (j, number) in buf
-            .split(' ')
-            .filter(|&s| !s.trim().is_empty())
-            .enumerate()
-            .take(5)
-        {
-            board[i][j] = u8::from_str_radix(number.trim(), 10)?;
+        let row = buf
+            .split_whitespace()
+            .map(|number| number.parse())
+            .collect::<Result<Vec<u32>, ParseIntError>>()?;
+        if let Some(first_row) = board.first() {
+            if row.len() != first_row.len() {
+                return Err(Box::new(InconsistentBoardError {
+                    row_length: row.len(),
+                    expected: first_row.len(),
+                }));
+            }
         }
+        board.push(row);
+    }
+    if board.is_empty() {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "Premature end of bingo board.",
+        )));
     }
-    return Ok(BingoBoard::new(&board));
+    Ok(BingoBoard::new(&board))
+}
+
+// Only the end of the input (or a blank tail) ends the boards.
+fn is_end_of_boards(error: &(dyn Error + 'static)) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|error| error.kind() == std::io::ErrorKind::UnexpectedEof)
 }
 
 #[derive(Debug)]
 struct BingoSubsystem {
-    random_numbers: Vec<u8>,
+    random_numbers: Vec<u32>,
     boards: Vec<BingoBoard>,
 }
 
@@ -81,31 +233,22 @@ struct BingoResult {
     losing_score: u64,
 }
 
-#[derive(Debug)]
-struct DepletedRandomNumbersError {}
-
-impl std::fmt::Display for DepletedRandomNumbersError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("Depleted all random numbers before all board numbers were marked.")
-    }
-}
-
-impl Error for DepletedRandomNumbersError {}
-
 impl BingoSubsystem {
     fn read<R: BufRead>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
         let mut number_line = String::default();
         reader.read_line(&mut number_line)?;
 
-        let random_numbers: Result<Vec<u8>, ParseIntError> = number_line
-            .split(',')
-            .map(|x| u8::from_str_radix(x.trim(), 10))
-            .collect();
+        let random_numbers: Result<Vec<u32>, ParseIntError> =
+            number_line.split(',').map(|x| x.trim().parse()).collect();
         let random_numbers = random_numbers?;
 
         let mut boards: Vec<BingoBoard> = Vec::new();
-        while let Ok(board) = parse_bingo_board(reader) {
-            boards.push(board);
+        loop {
+            match parse_bingo_board(reader) {
+                Ok(board) => boards.push(board),
+                Err(error) if is_end_of_boards(&*error) => break,
+                Err(error) => return Err(error),
+            }
         }
 
         Ok(Self {
@@ -114,48 +257,265 @@ impl BingoSubsystem {
         })
     }
 
-    fn play_bingo_against_squid(&mut self) -> Result<BingoResult, Box<dyn Error>> {
-        let mut winning_score: Option<u64> = None;
-        let mut losing_board_index: Option<usize> = None;
-        for &number in &self.random_numbers {
-            for board in &mut self.boards {
-                board.mark(number);
-            }
+    fn with_win_rule(mut self, win_rule: WinRule) -> Self {
+        self.boards = self
+            .boards
+            .into_iter()
+            .map(|board| board.with_win_rule(win_rule))
+            .collect();
+        self
+    }
+
+    fn replay(&self) -> Replay<'_> {
+        Replay {
+            numbers: self.random_numbers.iter().enumerate(),
+            boards: self.boards.clone(),
+        }
+    }
 
-            if winning_score.is_none() {
-                if let Some(winner) = self.boards.iter().find(|b| b.has_won()) {
-                    winning_score = Some(winner.score() * number as u64);
+    fn play(&self) -> GameReport {
+        let mut finishers: Vec<BoardResult> = Vec::new();
+        let mut has_finished = vec![false; self.boards.len()];
+        for draw in self.replay() {
+            let num_finished = finishers.len();
+            for (board, state) in draw.boards.iter().enumerate() {
+                if state.has_won && !has_finished[board] {
+                    has_finished[board] = true;
+                    finishers.push(BoardResult {
+                        board,
+                        rank: num_finished + 1,
+                        draw_index: draw.index,
+                        number: draw.number,
+                        score: state.unmarked_sum * draw.number as u64,
+                    });
                 }
             }
+            if finishers.len() == self.boards.len() {
+                break;
+            }
+        }
+        GameReport {
+            finishers,
+            non_winners: (0..self.boards.len())
+                .filter(|&board| !has_finished[board])
+                .collect(),
+        }
+    }
 
-            let non_won: Vec<(usize, &BingoBoard)> = self
+    // Ranks the boards as candidate cards by the draw completing them and
+    // estimates their chance to win over `simulations` random orders of the
+    // drawn numbers. Cards completing on the same draw split the win.
+    fn rank_cards(&self, simulations: usize, seed: u64) -> Vec<CardRanking> {
+        let mut wins = vec![0.0; self.boards.len()];
+        let mut rng = Rng::new(seed);
+        let mut numbers = self.random_numbers.clone();
+        for _ in 0..simulations {
+            rng.shuffle(&mut numbers);
+            let draws: Vec<Option<usize>> = self
                 .boards
                 .iter()
-                .enumerate()
-                .filter(|(_, board)| !board.has_won())
+                .map(|board| board.completion_draw(&numbers))
                 .collect();
-            if non_won.len() == 1 {
-                losing_board_index = Some(non_won[0].0);
+            if let Some(first) = draws.iter().flatten().min() {
+                let winners: Vec<usize> = (0..draws.len())
+                    .filter(|&card| draws[card] == Some(*first))
+                    .collect();
+                for &card in winners.iter() {
+                    wins[card] += 1.0 / winners.len() as f64;
+                }
             }
+        }
+
+        let mut rankings: Vec<CardRanking> = self
+            .boards
+            .iter()
+            .enumerate()
+            .map(|(card, board)| CardRanking {
+                card,
+                draw_index: board.completion_draw(&self.random_numbers),
+                win_probability: wins[card] / simulations.max(1) as f64,
+            })
+            .collect();
+        rankings.sort_by_key(|ranking| (ranking.draw_index.is_none(), ranking.draw_index));
+        rankings
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct CardRanking {
+    card: usize,
+    draw_index: Option<usize>,
+    win_probability: f64,
+}
+
+// A small xorshift generator, so that simulations can be repeated with the
+// same seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
 
-            if self.boards.iter().all(|b| b.has_won()) {
-                return Ok(BingoResult {
-                    winning_score: winning_score.unwrap(),
-                    losing_score: self.boards[losing_board_index.unwrap()].score() * number as u64,
-                });
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn shuffle<T>(&mut self, values: &mut [T]) {
+        for i in (1..values.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            values.swap(i, j);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct BoardResult {
+    board: usize,
+    // Boards winning on the same draw share a rank.
+    rank: usize,
+    draw_index: usize,
+    number: u32,
+    score: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct GameReport {
+    finishers: Vec<BoardResult>,
+    non_winners: Vec<usize>,
+}
+
+impl GameReport {
+    // The scores of the first and the last board to win, ignoring boards that
+    // never win. `None` if no board wins at all.
+    fn squid_result(&self) -> Option<BingoResult> {
+        Some(BingoResult {
+            winning_score: self.finishers.first()?.score,
+            losing_score: self.finishers.last()?.score,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct BoardState {
+    marked: Vec<Vec<bool>>,
+    has_won: bool,
+    unmarked_sum: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Draw {
+    index: usize,
+    number: u32,
+    boards: Vec<BoardState>,
+}
+
+// Yields the state of all boards after each drawn number.
+struct Replay<'a> {
+    numbers: std::iter::Enumerate<std::slice::Iter<'a, u32>>,
+    boards: Vec<BingoBoard>,
+}
+
+impl Iterator for Replay<'_> {
+    type Item = Draw;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, &number) = self.numbers.next()?;
+        for board in self.boards.iter_mut() {
+            board.mark(number);
+        }
+        Some(Draw {
+            index,
+            number,
+            boards: self
+                .boards
+                .iter()
+                .map(|board| BoardState {
+                    marked: board.marked(),
+                    has_won: board.has_won(),
+                    unmarked_sum: board.score(),
+                })
+                .collect(),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    win_rule: WinRule,
+    simulations: Option<usize>,
+    seed: u64,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options {
+        win_rule: WinRule::Lines,
+        simulations: None,
+        seed: 0,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--simulations" | "--seed" => {
+                let value = args.next().ok_or(MissingArgumentError(arg.clone()))?;
+                if arg == "--simulations" {
+                    options.simulations = Some(value.parse()?);
+                } else {
+                    options.seed = value.parse()?;
+                }
             }
+            _ => options.win_rule = arg.parse()?,
         }
+    }
+    Ok(options)
+}
 
-        return Err(Box::new(DepletedRandomNumbersError {}));
+#[derive(Debug)]
+struct MissingArgumentError(String);
+
+impl std::fmt::Display for MissingArgumentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Missing value for {}.", self.0)
     }
 }
 
+impl Error for MissingArgumentError {}
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let options = parse_args(env::args().skip(1))?;
     let stdin = io::stdin();
-    let mut bingo_subsystem = BingoSubsystem::read(&mut stdin.lock())?;
-    let result = bingo_subsystem.play_bingo_against_squid()?;
-    println!("Winning score: {}", result.winning_score);
-    println!("Winning score: {}", result.losing_score);
+    let bingo_subsystem = BingoSubsystem::read(&mut stdin.lock())?.with_win_rule(options.win_rule);
+    if let Some(simulations) = options.simulations {
+        for ranking in bingo_subsystem.rank_cards(simulations, options.seed) {
+            let draw = match ranking.draw_index {
+                Some(draw_index) => format!("completes after draw {}", draw_index),
+                None => "never completes".to_string(),
+            };
+            println!(
+                "Card {} {}, wins {:.1}% of random games",
+                ranking.card,
+                draw,
+                100.0 * ranking.win_probability
+            );
+        }
+        return Ok(());
+    }
+    let report = bingo_subsystem.play();
+    for result in report.finishers.iter() {
+        println!(
+            "Rank {}: board {} after draw {} (number {}) with score {}",
+            result.rank, result.board, result.draw_index, result.number, result.score
+        );
+    }
+    for board in report.non_winners.iter() {
+        println!("Board {} never won", board);
+    }
+    if let Some(result) = report.squid_result() {
+        println!("Winning score: {}", result.winning_score);
+        println!("Losing score: {}", result.losing_score);
+    }
     Ok(())
 }
 
@@ -220,11 +580,276 @@ mod tests {
         assert_eq!(board, new_test_board());
     }
 
+    #[test]
+    fn bingo_board_with_diagonal_marked_has_won_only_with_diagonals() {
+        let mut board = new_test_board();
+        for i in 1..=5 {
+            board.mark(10 * i + 6 - i);
+        }
+        assert!(!board.has_won());
+        let board = board.with_win_rule(WinRule::LinesAndDiagonals);
+        assert!(board.has_won());
+
+        let mut board = new_test_board().with_win_rule(WinRule::LinesAndDiagonals);
+        for i in 1..=4 {
+            board.mark(11 * i);
+        }
+        assert!(!board.has_won());
+        board.mark(55);
+        assert!(board.has_won());
+    }
+
+    #[test]
+    fn bingo_board_with_full_card_rule_needs_all_numbers() {
+        let mut board = new_test_board().with_win_rule(WinRule::FullCard);
+        for i in 1..=5 {
+            for j in 1..=5 {
+                assert!(!board.has_won());
+                board.mark(i * 10 + j);
+            }
+        }
+        assert!(board.has_won());
+    }
+
+    #[test]
+    fn bingo_board_with_four_corners_rule_needs_corners() {
+        let mut board = new_test_board().with_win_rule(WinRule::FourCorners);
+        for i in 1..=5 {
+            board.mark(10 + i);
+        }
+        assert!(!board.has_won());
+        board.mark(51);
+        assert!(!board.has_won());
+        board.mark(55);
+        assert!(board.has_won());
+    }
+
+    #[test]
+    fn parse_bingo_board_of_other_size_works() {
+        let mut input = "
+1000 2 3
+4 5 6
+
+7 8 9
+"
+        .as_bytes();
+        let mut board = parse_bingo_board(&mut input).unwrap();
+        assert_eq!(board, BingoBoard::new(&[[1000, 2, 3], [4, 5, 6]]));
+        for number in [1000, 5] {
+            board.mark(number);
+        }
+        assert!(!board.has_won());
+        board.mark(4);
+        assert!(board.has_won());
+        assert_eq!(board.score(), 2 + 3 + 6);
+
+        let board = parse_bingo_board(&mut input).unwrap();
+        assert_eq!(board, BingoBoard::new(&[[7, 8, 9]]));
+        assert!(parse_bingo_board(&mut input).is_err());
+    }
+
+    #[test]
+    fn parse_bingo_board_with_inconsistent_rows_fails() {
+        let mut input = "1 2 3
+4 5
+"
+        .as_bytes();
+        assert!(parse_bingo_board(&mut input).is_err());
+    }
+
+    #[test]
+    fn bingo_subsystem_with_ragged_board_fails() {
+        let mut input = "1,2\n\n1 2\n3 4\n\n5 6\n7\n\n8 9\n1 2\n".as_bytes();
+        let error = BingoSubsystem::read(&mut input).unwrap_err();
+        assert!(error.downcast_ref::<InconsistentBoardError>().is_some());
+
+        let mut input = "1,2\n\n1 2\n3 4\n\n\n".as_bytes();
+        assert_eq!(BingoSubsystem::read(&mut input).unwrap().boards.len(), 1);
+    }
+
+    #[test]
+    fn win_rule_from_str() {
+        assert_eq!("corners".parse::<WinRule>().unwrap(), WinRule::FourCorners);
+        assert!("row".parse::<WinRule>().is_err());
+    }
+
+    #[test]
+    fn bingo_subsystem_with_other_win_rules() -> Result<(), Box<dyn Error>> {
+        for (win_rule, winning_score, losing_score) in [
+            (WinRule::LinesAndDiagonals, 494, 1924),
+            (WinRule::FullCard, 0, 0),
+            (WinRule::FourCorners, 3262, 0),
+        ] {
+            let mut input = include_str!("../test.input").as_bytes();
+            let bingo_subsystem = BingoSubsystem::read(&mut input)?.with_win_rule(win_rule);
+            let result = bingo_subsystem.play().squid_result().unwrap();
+            assert_eq!(result.winning_score, winning_score);
+            assert_eq!(result.losing_score, losing_score);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn game_report_ranks_boards() -> Result<(), Box<dyn Error>> {
+        let mut input = include_str!("../test.input").as_bytes();
+        let bingo_subsystem = BingoSubsystem::read(&mut input)?;
+        assert_eq!(
+            bingo_subsystem.play(),
+            GameReport {
+                finishers: vec![
+                    BoardResult {
+                        board: 2,
+                        rank: 1,
+                        draw_index: 11,
+                        number: 24,
+                        score: 4512,
+                    },
+                    BoardResult {
+                        board: 0,
+                        rank: 2,
+                        draw_index: 13,
+                        number: 16,
+                        score: 2192,
+                    },
+                    BoardResult {
+                        board: 1,
+                        rank: 3,
+                        draw_index: 14,
+                        number: 13,
+                        score: 1924,
+                    },
+                ],
+                non_winners: vec![],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn game_report_lists_boards_that_never_win() -> Result<(), Box<dyn Error>> {
+        let mut input = "1,2,3,4\n\n1 2\n3 4\n\n1 5\n6 7\n\n2 1\n4 3".as_bytes();
+        let bingo_subsystem = BingoSubsystem::read(&mut input)?;
+        let report = bingo_subsystem.play();
+        let ranks: Vec<(usize, usize, usize)> = report
+            .finishers
+            .iter()
+            .map(|result| (result.board, result.rank, result.draw_index))
+            .collect();
+        assert_eq!(ranks, vec![(0, 1, 1), (2, 1, 1)]);
+        assert_eq!(report.non_winners, vec![1]);
+        assert_eq!(
+            report.squid_result(),
+            Some(BingoResult {
+                winning_score: 14,
+                losing_score: 14,
+            })
+        );
+
+        let mut input = "1\n\n2 3\n4 5".as_bytes();
+        let report = BingoSubsystem::read(&mut input)?.play();
+        assert_eq!(report.non_winners, vec![0]);
+        assert_eq!(report.squid_result(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn replay_yields_marked_boards_after_each_draw() -> Result<(), Box<dyn Error>> {
+        let mut input = "4,1,3,2\n\n1 2\n3 4".as_bytes();
+        let bingo_subsystem = BingoSubsystem::read(&mut input)?;
+        let draws: Vec<Draw> = bingo_subsystem.replay().collect();
+        assert_eq!(draws.len(), 4);
+        assert_eq!(
+            draws[1],
+            Draw {
+                index: 1,
+                number: 1,
+                boards: vec![BoardState {
+                    marked: vec![vec![true, false], vec![false, true]],
+                    has_won: false,
+                    unmarked_sum: 5,
+                }],
+            }
+        );
+        assert!(draws[2].boards[0].has_won);
+        assert_eq!(draws[3].boards[0].marked, vec![vec![true; 2]; 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn completion_draw_finds_first_winning_draw() {
+        let board = new_test_board();
+        assert_eq!(
+            board.completion_draw(&[11, 99, 21, 31, 41, 12, 51]),
+            Some(6)
+        );
+        assert_eq!(board.completion_draw(&[11, 12]), None);
+    }
+
+    #[test]
+    fn rank_cards_orders_by_completion_and_estimates_win_probability() -> Result<(), Box<dyn Error>>
+    {
+        let mut input = include_str!("../test.input").as_bytes();
+        let bingo_subsystem = BingoSubsystem::read(&mut input)?;
+        let rankings = bingo_subsystem.rank_cards(1000, 42);
+        let order: Vec<(usize, Option<usize>)> = rankings
+            .iter()
+            .map(|ranking| (ranking.card, ranking.draw_index))
+            .collect();
+        assert_eq!(order, vec![(2, Some(11)), (0, Some(13)), (1, Some(14))]);
+        let total: f64 = rankings.iter().map(|ranking| ranking.win_probability).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+        for ranking in rankings.iter() {
+            assert!(ranking.win_probability > 0.1);
+        }
+        assert_eq!(bingo_subsystem.rank_cards(1000, 42), rankings);
+        assert_ne!(bingo_subsystem.rank_cards(1000, 43), rankings);
+        Ok(())
+    }
+
+    #[test]
+    fn rank_cards_puts_cards_that_never_complete_last() -> Result<(), Box<dyn Error>> {
+        let mut input = "1,2,3,4\n\n1 5\n6 7\n\n1 2\n3 4".as_bytes();
+        let bingo_subsystem = BingoSubsystem::read(&mut input)?;
+        let rankings = bingo_subsystem.rank_cards(100, 1);
+        assert_eq!(
+            rankings,
+            vec![
+                CardRanking {
+                    card: 1,
+                    draw_index: Some(1),
+                    win_probability: 1.0,
+                },
+                CardRanking {
+                    card: 0,
+                    draw_index: None,
+                    win_probability: 0.0,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_args_works() {
+        let args = ["diagonals", "--simulations", "100", "--seed", "7"]
+            .iter()
+            .map(|arg| arg.to_string());
+        assert_eq!(
+            parse_args(args).unwrap(),
+            Options {
+                win_rule: WinRule::LinesAndDiagonals,
+                simulations: Some(100),
+                seed: 7,
+            }
+        );
+        assert!(parse_args(["--seed"].iter().map(|arg| arg.to_string())).is_err());
+    }
+
     #[test]
     fn bingo_subsystem() -> Result<(), Box<dyn Error>> {
         let mut input = include_str!("../test.input").as_bytes();
-        let mut bingo_subsystem = BingoSubsystem::read(&mut input)?;
-        let result = bingo_subsystem.play_bingo_against_squid()?;
+        let bingo_subsystem = BingoSubsystem::read(&mut input)?;
+        let result = bingo_subsystem.play().squid_result().unwrap();
         assert_eq!(result.winning_score, 4512);
         assert_eq!(result.losing_score, 1924);
         Ok(())
@@ -1,61 +1,493 @@
-use std::collections::VecDeque;
+use std::env;
 use std::error::Error;
+use std::fmt::{Debug, Display};
 use std::io::{self, BufRead};
 use std::num::ParseIntError;
 
-pub struct FishSchool {
-    cycle: usize,
-    adult_fish: [u64; 7],
-    young_fish: VecDeque<u64>
+use num_bigint::BigUint;
+
+#[derive(PartialEq, Eq)]
+pub struct OverflowError;
+
+// Returned from main, so the hint is shown instead of the type name.
+impl Debug for OverflowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
 }
 
-impl FishSchool {
-    fn new(initial_timers: Vec<u8>) -> Self {
-        let mut fish_school = Self {
-            cycle: 0,
-            adult_fish: [0; 7],
-            young_fish: VecDeque::from(vec![0, 0])
-        };
-        for timer in initial_timers {
-            fish_school.adult_fish[timer as usize] += 1;
+impl Display for OverflowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fish count overflows u64, use --exact or --modulus")
+    }
+}
+
+impl Error for OverflowError {}
+
+// The number type used to count fish. As the modulus of `Modular` is only
+// known at runtime, zero and one are derived from an existing value.
+pub trait Count: Clone + Debug + PartialEq {
+    fn zero(&self) -> Self;
+    fn one(&self) -> Self;
+    fn of(&self, n: u64) -> Self;
+    fn add(&self, other: &Self) -> Result<Self, OverflowError>;
+    fn mul(&self, other: &Self) -> Result<Self, OverflowError>;
+}
+
+impl Count for u64 {
+    fn zero(&self) -> Self {
+        0
+    }
+
+    fn one(&self) -> Self {
+        1
+    }
+
+    fn of(&self, n: u64) -> Self {
+        n
+    }
+
+    fn add(&self, other: &Self) -> Result<Self, OverflowError> {
+        self.checked_add(*other).ok_or(OverflowError)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, OverflowError> {
+        self.checked_mul(*other).ok_or(OverflowError)
+    }
+}
+
+impl Count for BigUint {
+    fn zero(&self) -> Self {
+        BigUint::from(0u8)
+    }
+
+    fn one(&self) -> Self {
+        BigUint::from(1u8)
+    }
+
+    fn of(&self, n: u64) -> Self {
+        BigUint::from(n)
+    }
+
+    fn add(&self, other: &Self) -> Result<Self, OverflowError> {
+        Ok(self + other)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, OverflowError> {
+        Ok(self * other)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Modular {
+    value: u64,
+    modulus: u64,
+}
+
+impl Modular {
+    fn new(value: u64, modulus: u64) -> Self {
+        Self {
+            value: value % modulus,
+            modulus,
         }
-        return fish_school
     }
-    
-    fn step(&mut self) {
-        let i = self.cycle % 7;
-        let num_fish_to_spawn = self.adult_fish[i];
-        self.young_fish.push_back(num_fish_to_spawn);
-        self.adult_fish[i] += self.young_fish.pop_front().unwrap();
-        self.cycle += 1;
+}
+
+impl Count for Modular {
+    fn zero(&self) -> Self {
+        Self::new(0, self.modulus)
+    }
+
+    fn one(&self) -> Self {
+        Self::new(1, self.modulus)
+    }
+
+    fn of(&self, n: u64) -> Self {
+        Self::new(n, self.modulus)
+    }
+
+    fn add(&self, other: &Self) -> Result<Self, OverflowError> {
+        Ok(Self::new(
+            ((self.value as u128 + other.value as u128) % self.modulus as u128) as u64,
+            self.modulus,
+        ))
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, OverflowError> {
+        Ok(Self::new(
+            ((self.value as u128 * other.value as u128) % self.modulus as u128) as u64,
+            self.modulus,
+        ))
     }
-    
-    fn step_n(&mut self, n: usize) {
-        for _ in 0..n {
-            self.step()
+}
+
+impl Display for Modular {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (mod {})", self.value, self.modulus)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Matrix<T: Count>(Vec<Vec<T>>);
+
+impl<T: Count> Matrix<T> {
+    fn identity(size: usize, one: &T) -> Self {
+        Self(
+            (0..size)
+                .map(|i| {
+                    (0..size)
+                        .map(|j| if i == j { one.one() } else { one.zero() })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, OverflowError> {
+        let rows = self
+            .0
+            .iter()
+            .map(|row| {
+                (0..other.0.len())
+                    .map(|j| {
+                        row.iter()
+                            .zip(other.0.iter())
+                            .try_fold(row[0].zero(), |sum, (a, b)| sum.add(&a.mul(&b[j])?))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self(rows))
+    }
+
+    fn apply(&self, vector: &[T]) -> Result<Vec<T>, OverflowError> {
+        self.0
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(vector.iter())
+                    .try_fold(row[0].zero(), |sum, (a, b)| sum.add(&a.mul(b)?))
+            })
+            .collect()
+    }
+
+    // Exponentiation by squaring. The powers bound the growth of any state,
+    // so they may overflow slightly before the fish count does.
+    fn pow(&self, mut n: u64) -> Result<Self, OverflowError> {
+        let mut result = Self::identity(self.0.len(), &self.0[0][0]);
+        let mut power = self.clone();
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.mul(&power)?;
+            }
+            n >>= 1;
+            if n > 0 {
+                power = power.mul(&power)?;
+            }
         }
+        Ok(result)
     }
-    
-    fn fish_count(&self) -> u64 {
-        let num_adult_fish: u64 = self.adult_fish.iter().sum();
-        let num_young_fish: u64 = self.young_fish.iter().sum();
-        num_adult_fish + num_young_fish
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LifeCycle {
+    // Days between two spawns of an adult fish.
+    pub adult_period: usize,
+    // Additional days before a newborn fish spawns for the first time.
+    pub juvenile_delay: usize,
+    pub offspring: u64,
+    // Fish are removed on the day they reach this age (after spawning).
+    pub mortality: Option<usize>,
+}
+
+impl Default for LifeCycle {
+    fn default() -> Self {
+        Self {
+            adult_period: 7,
+            juvenile_delay: 2,
+            offspring: 1,
+            mortality: None,
+        }
+    }
+}
+
+impl LifeCycle {
+    fn newborn_timer(&self) -> usize {
+        self.adult_period + self.juvenile_delay - 1
+    }
+
+    // Without mortality, the state of a fish is its timer. Otherwise it is its
+    // age, as fish with the same timer may have different ages.
+    fn num_states(&self) -> usize {
+        self.mortality.unwrap_or_else(|| self.newborn_timer() + 1)
+    }
+
+    fn timer(&self, state: usize) -> usize {
+        match self.mortality {
+            None => state,
+            Some(_) if state < self.adult_period + self.juvenile_delay => {
+                self.newborn_timer() - state
+            }
+            Some(_) => {
+                self.adult_period - 1 - (state - self.newborn_timer() - 1) % self.adult_period
+            }
+        }
+    }
+
+    // Initial fish are assumed to be as young as their timer allows.
+    fn initial_state(&self, timer: usize) -> Option<usize> {
+        match self.mortality {
+            None => Some(timer),
+            Some(age) => Some(self.newborn_timer() - timer).filter(|&state| state < age),
+        }
+    }
+
+    // The states a fish in `state` leads to on the next day with multiplicities.
+    fn successors(&self, state: usize) -> Vec<(usize, u64)> {
+        let spawns = self.timer(state) == 0;
+        let mut successors = vec![];
+        match self.mortality {
+            None if spawns => successors.push((self.adult_period - 1, 1)),
+            None => successors.push((state - 1, 1)),
+            Some(age) if state + 1 < age => successors.push((state + 1, 1)),
+            Some(_) => (),
+        }
+        if spawns {
+            let newborn = match self.mortality {
+                None => self.newborn_timer(),
+                Some(_) => 0,
+            };
+            successors.push((newborn, self.offspring));
+        }
+        successors
+    }
+
+    fn transition<T: Count>(&self, one: &T) -> Result<Matrix<T>, OverflowError> {
+        let mut matrix = Matrix(vec![vec![one.zero(); self.num_states()]; self.num_states()]);
+        for state in 0..self.num_states() {
+            for (successor, multiplicity) in self.successors(state) {
+                let entry = &mut matrix.0[successor][state];
+                *entry = entry.add(&one.of(multiplicity))?;
+            }
+        }
+        Ok(matrix)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidLifeCycleError(&'static str);
+
+impl Display for InvalidLifeCycleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid life cycle: {}", self.0)
+    }
+}
+
+impl Error for InvalidLifeCycleError {}
+
+#[derive(Debug)]
+pub struct InvalidTimerError(u8);
+
+impl Display for InvalidTimerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid timer {}", self.0)
+    }
+}
+
+impl Error for InvalidTimerError {}
+
+// Counts the fish per state of the life cycle.
+pub struct FishSchool<T: Count = u64> {
+    life_cycle: LifeCycle,
+    states: Vec<T>,
+}
+
+impl<T: Count> FishSchool<T> {
+    // `one` determines the number type, e.g. `Modular::new(1, p)`.
+    fn new(life_cycle: LifeCycle, initial_timers: &[u8], one: T) -> Result<Self, Box<dyn Error>> {
+        if life_cycle.adult_period == 0 {
+            return Err(InvalidLifeCycleError("adult period must be positive").into());
+        }
+        if life_cycle.mortality == Some(0) {
+            return Err(InvalidLifeCycleError("mortality age must be positive").into());
+        }
+        let mut states = vec![one.zero(); life_cycle.num_states()];
+        for &timer in initial_timers {
+            if timer as usize > life_cycle.newborn_timer() {
+                return Err(InvalidTimerError(timer).into());
+            }
+            if let Some(state) = life_cycle.initial_state(timer as usize) {
+                states[state] = states[state].add(&one)?;
+            }
+        }
+        Ok(Self { life_cycle, states })
+    }
+
+    fn step(&mut self) -> Result<(), OverflowError> {
+        let mut states = vec![self.states[0].zero(); self.states.len()];
+        for (state, count) in self.states.iter().enumerate() {
+            for (successor, multiplicity) in self.life_cycle.successors(state) {
+                states[successor] = states[successor].add(&count.mul(&count.of(multiplicity))?)?;
+            }
+        }
+        self.states = states;
+        Ok(())
+    }
+
+    fn step_n(&mut self, n: u64) -> Result<(), OverflowError> {
+        // Stepping is cheaper than squaring matrices for a few days.
+        if n < self.states.len() as u64 {
+            for _ in 0..n {
+                self.step()?;
+            }
+            return Ok(());
+        }
+        let transition = self.life_cycle.transition(&self.states[0])?;
+        self.states = transition.pow(n)?.apply(&self.states)?;
+        Ok(())
+    }
+
+    fn fish_count(&self) -> Result<T, OverflowError> {
+        self.states
+            .iter()
+            .try_fold(self.states[0].zero(), |sum, count| sum.add(count))
+    }
+
+    // The number of fish per timer value.
+    fn histogram(&self) -> Result<Vec<T>, OverflowError> {
+        let mut histogram = vec![self.states[0].zero(); self.life_cycle.newborn_timer() + 1];
+        for (state, count) in self.states.iter().enumerate() {
+            let timer = self.life_cycle.timer(state);
+            histogram[timer] = histogram[timer].add(count)?;
+        }
+        Ok(histogram)
+    }
+
+    // The histograms after each of the given (ascending) numbers of days.
+    fn histograms(&mut self, days: &[u64]) -> Result<Vec<Vec<T>>, OverflowError> {
+        let mut day = 0;
+        days.iter()
+            .map(|&target| {
+                self.step_n(target - day)?;
+                day = target;
+                self.histogram()
+            })
+            .collect()
     }
 }
 
 fn parse_input<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, Box<dyn Error>> {
     let mut buf = String::new();
     reader.read_line(&mut buf)?;
-    Ok(buf.split(',').map(|x| u8::from_str_radix(x.trim(), 10)).collect::<Result<Vec<u8>, ParseIntError>>()?)
+    let timers = buf
+        .split(',')
+        .map(|x| x.trim().parse())
+        .collect::<Result<Vec<u8>, ParseIntError>>()?;
+    Ok(timers)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Counting {
+    Native,
+    Exact,
+    Modulo(u64),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    days: Vec<u64>,
+    counting: Counting,
+    life_cycle: LifeCycle,
+    histogram: bool,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options {
+        days: vec![],
+        counting: Counting::Native,
+        life_cycle: LifeCycle::default(),
+        histogram: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--exact" => options.counting = Counting::Exact,
+            "--modulus" => {
+                let modulus = args.next().ok_or("missing modulus")?.parse()?;
+                if modulus == 0 {
+                    return Err("modulus must be positive".into());
+                }
+                options.counting = Counting::Modulo(modulus);
+            }
+            "--adult-period" => {
+                options.life_cycle.adult_period =
+                    args.next().ok_or("missing adult period")?.parse()?
+            }
+            "--juvenile-delay" => {
+                options.life_cycle.juvenile_delay =
+                    args.next().ok_or("missing juvenile delay")?.parse()?
+            }
+            "--offspring" => {
+                options.life_cycle.offspring = args.next().ok_or("missing offspring")?.parse()?
+            }
+            "--mortality" => {
+                options.life_cycle.mortality =
+                    Some(args.next().ok_or("missing mortality age")?.parse()?)
+            }
+            "--histogram" => options.histogram = true,
+            _ => options.days.push(arg.parse()?),
+        }
+    }
+    if options.days.is_empty() {
+        options.days = vec![80, 256];
+    }
+    Ok(options)
+}
+
+fn print_counts<T: Count + Display>(
+    mut fish_school: FishSchool<T>,
+    days: &[u64],
+    histogram: bool,
+) -> Result<(), OverflowError> {
+    let mut days = days.to_vec();
+    days.sort_unstable();
+    if histogram {
+        for (day, histogram) in days.iter().zip(fish_school.histograms(&days)?) {
+            let counts: Vec<String> = histogram.iter().map(|count| count.to_string()).collect();
+            println!("Day {}: {}", day, counts.join(","));
+        }
+        return Ok(());
+    }
+    let mut day = 0;
+    for target in days {
+        fish_school.step_n(target - day)?;
+        day = target;
+        println!("After {} days: {}", day, fish_school.fish_count()?);
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let options = parse_args(env::args().skip(1))?;
     let stdin = io::stdin();
-    let mut fish_school = FishSchool::new(parse_input(&mut stdin.lock())?);
-    fish_school.step_n(80);
-    println!("After 80 days: {}", fish_school.fish_count());
-    fish_school.step_n(256 - 80);
-    println!("After 256 days: {}", fish_school.fish_count());
+    let initial_timers = parse_input(&mut stdin.lock())?;
+    let (life_cycle, days, histogram) = (options.life_cycle, &options.days, options.histogram);
+    match options.counting {
+        Counting::Native => print_counts(
+            FishSchool::new(life_cycle, &initial_timers, 1u64)?,
+            days,
+            histogram,
+        )?,
+        Counting::Exact => print_counts(
+            FishSchool::new(life_cycle, &initial_timers, BigUint::from(1u8))?,
+            days,
+            histogram,
+        )?,
+        Counting::Modulo(modulus) => print_counts(
+            FishSchool::new(life_cycle, &initial_timers, Modular::new(1, modulus))?,
+            days,
+            histogram,
+        )?,
+    }
     Ok(())
 }
 
@@ -63,21 +495,322 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod tests {
     use super::*;
 
+    fn example<T: Count>(one: T) -> FishSchool<T> {
+        FishSchool::new(LifeCycle::default(), &[3, 4, 3, 1, 2], one).unwrap()
+    }
+
+    // Tracks the timer and age of every single fish and returns the histograms.
+    fn simulate(life_cycle: LifeCycle, initial_timers: &[u8], days: u64) -> Vec<Vec<u64>> {
+        let newborn_timer = life_cycle.newborn_timer();
+        let mut fish: Vec<(usize, usize)> = initial_timers
+            .iter()
+            .map(|&timer| (timer as usize, newborn_timer - timer as usize))
+            .collect();
+        let is_alive = |age: usize| life_cycle.mortality.is_none_or(|mortality| age < mortality);
+        fish.retain(|&(_, age)| is_alive(age));
+        let histogram = |fish: &[(usize, usize)]| {
+            let mut histogram = vec![0; newborn_timer + 1];
+            for &(timer, _) in fish {
+                histogram[timer] += 1;
+            }
+            histogram
+        };
+        let mut histograms = vec![histogram(&fish)];
+        for _ in 0..days {
+            let mut newborns = vec![];
+            for (timer, age) in fish.iter_mut() {
+                if *timer == 0 {
+                    *timer = life_cycle.adult_period - 1;
+                    newborns.extend((0..life_cycle.offspring).map(|_| (newborn_timer, 0)));
+                } else {
+                    *timer -= 1;
+                }
+                *age += 1;
+            }
+            fish.retain(|&(_, age)| is_alive(age));
+            fish.extend(newborns);
+            histograms.push(histogram(&fish));
+        }
+        histograms
+    }
+
     #[test]
     fn test_fish_school() {
-        let mut fish_school = FishSchool::new(vec![3, 4, 3, 1, 2]);
-        fish_school.step_n(18);
-        assert_eq!(fish_school.fish_count(), 26);
-        fish_school.step_n(80 - 18);
-        assert_eq!(fish_school.fish_count(), 5934);
-        fish_school.step_n(256 - 80);
-        assert_eq!(fish_school.fish_count(), 26984457539);
-    }
-    
+        let mut fish_school = example(1u64);
+        fish_school.step_n(18).unwrap();
+        assert_eq!(fish_school.fish_count().unwrap(), 26);
+        fish_school.step_n(80 - 18).unwrap();
+        assert_eq!(fish_school.fish_count().unwrap(), 5934);
+        fish_school.step_n(256 - 80).unwrap();
+        assert_eq!(fish_school.fish_count().unwrap(), 26984457539);
+    }
+
+    #[test]
+    fn test_step_matches_step_n() {
+        let mut stepped = example(1u64);
+        for day in 0..100 {
+            let mut fish_school = example(1u64);
+            fish_school.step_n(day).unwrap();
+            assert_eq!(fish_school.states, stepped.states);
+            stepped.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_exact_count() {
+        let mut fish_school = example(BigUint::from(1u8));
+        fish_school.step_n(256).unwrap();
+        assert_eq!(
+            fish_school.fish_count().unwrap(),
+            BigUint::from(26984457539u64)
+        );
+        fish_school.step_n(1000 - 256).unwrap();
+        let count = fish_school.fish_count().unwrap();
+        assert!(count > BigUint::from(u64::MAX));
+        assert_eq!(count.to_string().len(), 39);
+    }
+
+    #[test]
+    fn test_overflow() {
+        // After 500 days, the counts per state still fit but their sum does not.
+        let mut fish_school = example(1u64);
+        fish_school.step_n(500).unwrap();
+        assert_eq!(fish_school.fish_count(), Err(OverflowError));
+        let mut fish_school = example(1u64);
+        assert_eq!(fish_school.step_n(600), Err(OverflowError));
+        let mut fish_school = example(1u64);
+        assert_eq!(fish_school.histograms(&[80, 600]), Err(OverflowError));
+        let mut fish_school = example(1u64);
+        fish_school.step_n(400).unwrap();
+        assert_eq!(
+            (0..200).try_for_each(|_| fish_school.step()),
+            Err(OverflowError)
+        );
+    }
+
+    #[test]
+    fn test_modular_count() {
+        let modulus = 1_000_000_007;
+        let mut fish_school = example(Modular::new(1, modulus));
+        fish_school.step_n(256).unwrap();
+        assert_eq!(
+            fish_school.fish_count().unwrap(),
+            Modular::new(26984457539, modulus)
+        );
+
+        let mut exact = example(BigUint::from(1u8));
+        exact.step_n(1000).unwrap();
+        let mut modular = example(Modular::new(1, modulus));
+        modular.step_n(1000).unwrap();
+        assert_eq!(
+            modular.fish_count().unwrap(),
+            Modular::new(
+                (exact.fish_count().unwrap() % BigUint::from(modulus))
+                    .to_u64_digits()
+                    .first()
+                    .copied()
+                    .unwrap_or(0),
+                modulus
+            )
+        );
+
+        // Only feasible in O(log n).
+        let mut fish_school = example(Modular::new(1, modulus));
+        fish_school.step_n(1_000_000_000_000).unwrap();
+        assert!(fish_school.fish_count().unwrap().value < modulus);
+    }
+
     #[test]
     fn test_parse_input() {
         let mut input: &[u8] = "3,4,3,1,2\n".as_bytes();
         assert_eq!(parse_input(&mut input).unwrap(), vec![3, 4, 3, 1, 2]);
+        let mut input: &[u8] = "3,a\n".as_bytes();
+        assert!(parse_input(&mut input).is_err());
     }
-}
 
+    #[test]
+    fn test_invalid_configuration() {
+        assert!(FishSchool::new(LifeCycle::default(), &[3, 9], 1u64).is_err());
+        let life_cycle = LifeCycle {
+            juvenile_delay: 3,
+            ..LifeCycle::default()
+        };
+        assert!(FishSchool::new(life_cycle, &[3, 9], 1u64).is_ok());
+        let life_cycle = LifeCycle {
+            adult_period: 0,
+            ..LifeCycle::default()
+        };
+        assert!(FishSchool::new(life_cycle, &[0], 1u64).is_err());
+        let life_cycle = LifeCycle {
+            mortality: Some(0),
+            ..LifeCycle::default()
+        };
+        assert!(FishSchool::new(life_cycle, &[0], 1u64).is_err());
+    }
+
+    #[test]
+    fn test_histograms() {
+        let histograms = example(1u64).histograms(&[0, 1, 2]).unwrap();
+        assert_eq!(
+            histograms,
+            vec![
+                vec![0, 1, 1, 2, 1, 0, 0, 0, 0],
+                vec![1, 1, 2, 1, 0, 0, 0, 0, 0],
+                vec![1, 2, 1, 0, 0, 0, 1, 0, 1],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_histograms_skip_days() {
+        let mut fish_school = example(1u64);
+        assert_eq!(
+            fish_school.histograms(&[18, 80]).unwrap(),
+            vec![
+                vec![3, 5, 3, 2, 2, 1, 5, 1, 4],
+                vec![424, 729, 558, 790, 739, 762, 991, 370, 571],
+            ]
+        );
+        let mut fish_school = example(Modular::new(1, 1_000_000_007));
+        let histogram = &fish_school.histograms(&[1_000_000_000_000]).unwrap()[0];
+        assert_eq!(histogram.len(), 9);
+    }
+
+    #[test]
+    fn test_life_cycles() {
+        let initial_timers = [0, 1, 3, 4, 5];
+        let life_cycles = [
+            LifeCycle::default(),
+            LifeCycle {
+                mortality: Some(20),
+                ..LifeCycle::default()
+            },
+            LifeCycle {
+                adult_period: 3,
+                juvenile_delay: 4,
+                offspring: 2,
+                mortality: None,
+            },
+            LifeCycle {
+                adult_period: 3,
+                juvenile_delay: 4,
+                offspring: 2,
+                mortality: Some(12),
+            },
+            LifeCycle {
+                adult_period: 1,
+                juvenile_delay: 0,
+                offspring: 1,
+                mortality: Some(3),
+            },
+            LifeCycle {
+                adult_period: 6,
+                juvenile_delay: 0,
+                offspring: 3,
+                mortality: Some(4),
+            },
+        ];
+        for &life_cycle in life_cycles.iter() {
+            let initial_timers: Vec<u8> = initial_timers
+                .iter()
+                .copied()
+                .filter(|&timer| timer as usize <= life_cycle.newborn_timer())
+                .collect();
+            let mut stepped = FishSchool::new(life_cycle, &initial_timers, 1u64).unwrap();
+            let expected = simulate(life_cycle, &initial_timers, 20);
+            assert_eq!(
+                FishSchool::new(life_cycle, &initial_timers, 1u64)
+                    .unwrap()
+                    .histograms(&(0..=20).collect::<Vec<u64>>())
+                    .unwrap(),
+                expected
+            );
+            for (days, expected) in expected.into_iter().enumerate() {
+                let days = days as u64;
+                let mut fish_school = FishSchool::new(life_cycle, &initial_timers, 1u64).unwrap();
+                fish_school.step_n(days).unwrap();
+                assert_eq!(
+                    fish_school.histogram().unwrap(),
+                    expected,
+                    "{:?} {}",
+                    life_cycle,
+                    days
+                );
+                assert_eq!(
+                    stepped.histogram().unwrap(),
+                    expected,
+                    "{:?} {}",
+                    life_cycle,
+                    days
+                );
+                stepped.step().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_mortality_bounds_population() {
+        // A fish spawning once before dying keeps the population constant.
+        let life_cycle = LifeCycle {
+            mortality: Some(10),
+            ..LifeCycle::default()
+        };
+        let mut fish_school = FishSchool::new(life_cycle, &[8], 1u64).unwrap();
+        fish_school.step_n(1_000_000).unwrap();
+        assert_eq!(fish_school.fish_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = ["1000000000000", "--modulus", "97"]
+            .iter()
+            .map(|arg| arg.to_string());
+        assert_eq!(
+            parse_args(args).unwrap(),
+            Options {
+                days: vec![1_000_000_000_000],
+                counting: Counting::Modulo(97),
+                life_cycle: LifeCycle::default(),
+                histogram: false,
+            }
+        );
+        assert_eq!(
+            parse_args(std::iter::empty()).unwrap(),
+            Options {
+                days: vec![80, 256],
+                counting: Counting::Native,
+                life_cycle: LifeCycle::default(),
+                histogram: false,
+            }
+        );
+        let args = [
+            "10",
+            "--adult-period",
+            "5",
+            "--juvenile-delay",
+            "1",
+            "--offspring",
+            "2",
+            "--mortality",
+            "30",
+            "--histogram",
+        ]
+        .iter()
+        .map(|arg| arg.to_string());
+        assert_eq!(
+            parse_args(args).unwrap(),
+            Options {
+                days: vec![10],
+                counting: Counting::Native,
+                life_cycle: LifeCycle {
+                    adult_period: 5,
+                    juvenile_delay: 1,
+                    offspring: 2,
+                    mortality: Some(30),
+                },
+                histogram: true,
+            }
+        );
+        assert!(parse_args(["--modulus", "0"].iter().map(|arg| arg.to_string())).is_err());
+    }
+}
@@ -1,70 +1,286 @@
-use std::{collections::HashMap, io::{BufRead, self}, error::Error, num::ParseIntError};
-
-fn least_fuel_constant(horizontal_positions: &[u64]) -> u64 {
-    let mut num_left = 0;
-    let mut num_right_buckets = HashMap::<u64, u64>::with_capacity(horizontal_positions.len());
-    
-    for &hpos in horizontal_positions {
-        num_right_buckets.entry(hpos).and_modify(|x| *x += 1).or_insert(1);
-    }
-    
-    let mut num_current = num_right_buckets.remove(&0).unwrap_or(0);
-    let mut num_right: u64 = num_right_buckets.values().sum();
-
-    let mut fuel = num_right_buckets.iter().map(|(pos, count)| pos * count).sum();
-    let mut min_fuel = fuel;
-
-    for hpos in 1..=(*num_right_buckets.keys().max().unwrap_or(&0)) {
-        num_left += num_current;
-        fuel += num_left;
-        
-        fuel -= num_right;
-        num_current = num_right_buckets.remove(&hpos).unwrap_or(0);
-        num_right -= num_current;
-        
-        if fuel < min_fuel {
-            min_fuel = fuel
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fmt::{Debug, Display},
+    io::{self, BufRead},
+};
+
+// Fuel needed to move a crab by a given distance.
+pub enum FuelCost {
+    // One unit of fuel per step.
+    Linear,
+    // Each step costs one more than the previous one.
+    Triangular,
+    // Any convex and non-decreasing cost of the distance.
+    Convex(Box<dyn Fn(u64) -> u64>),
+}
+
+impl FuelCost {
+    fn fuel(&self, distance: u64) -> u64 {
+        match self {
+            FuelCost::Linear => distance,
+            FuelCost::Triangular => distance * (distance + 1) / 2, // Gauß ftw
+            FuelCost::Convex(cost) => cost(distance),
+        }
+    }
+}
+
+// The weight is the fuel a crab needs per unit of the cost.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crab<P = i64> {
+    position: P,
+    weight: u64,
+}
+
+impl<P> From<P> for Crab<P> {
+    fn from(position: P) -> Self {
+        Self {
+            position,
+            weight: 1,
         }
     }
-    
-    min_fuel
 }
 
-fn least_fuel_linear(horizontal_positions: &[u64]) -> u64 {
-    let mut buckets = HashMap::<u64, u64>::with_capacity(horizontal_positions.len());
-    
-    for &hpos in horizontal_positions {
-        buckets.entry(hpos).and_modify(|x| *x += 1).or_insert(1);
+#[derive(Debug, PartialEq)]
+pub struct Alignment<P = i64, F = u64> {
+    position: P,
+    fuel: F,
+}
+
+// Sorted positions with the total weight of the crabs at each one.
+struct Buckets(Vec<(i64, u64)>);
+
+impl Buckets {
+    fn new(crabs: &[Crab]) -> Self {
+        let mut buckets = BTreeMap::new();
+        for crab in crabs {
+            *buckets.entry(crab.position).or_insert(0) += crab.weight;
+        }
+        Self(buckets.into_iter().collect())
+    }
+
+    fn fuel(&self, target: i64, cost: &FuelCost) -> u64 {
+        self.0
+            .iter()
+            .map(|&(hpos, count)| cost.fuel((hpos - target).unsigned_abs()) * count)
+            .sum()
     }
-    
-    let mut min_fuel = buckets.iter().map(|(pos, count)| (pos * (pos + 1)) / 2 * count).sum();
 
-    for hpos in 1..=(*buckets.keys().max().unwrap_or(&0)) {
-        let fuel = buckets.iter().map(|(&pos, &count)| {
-            let x = (pos as i64 - hpos as i64).abs() as u64;
-            (x * (x + 1)) / 2 * count  // Gauß ftw
-        }).sum();
+    fn alignment(&self, position: i64, cost: &FuelCost) -> Alignment {
+        Alignment {
+            position,
+            fuel: self.fuel(position, cost),
+        }
+    }
 
-        
-        if fuel < min_fuel {
-            min_fuel = fuel
+    fn median(&self) -> i64 {
+        let total: u64 = self.0.iter().map(|(_, count)| count).sum();
+        let mut seen = 0;
+        for &(hpos, count) in &self.0 {
+            seen += count;
+            if 2 * seen >= total {
+                return hpos;
+            }
         }
+        unreachable!("buckets are not empty")
+    }
+
+    // The optimum is within 1/2 of the (weighted) mean.
+    fn near_mean(&self, cost: &FuelCost) -> Alignment {
+        let total: u64 = self.0.iter().map(|(_, count)| count).sum();
+        let sum: i64 = self
+            .0
+            .iter()
+            .map(|&(hpos, count)| hpos * count as i64)
+            .sum();
+        let mean = sum.div_euclid(total as i64);
+        (mean - 1..=mean + 1)
+            .map(|position| self.alignment(position, cost))
+            .min_by_key(|alignment| alignment.fuel)
+            .unwrap()
+    }
+
+    // Ternary search over the integers, i.e. bisection on the sign of the
+    // slope, which finds the leftmost minimum of a convex function.
+    fn ternary_search(&self, cost: &FuelCost) -> Alignment {
+        let (mut low, mut high) = (self.0[0].0, self.0[self.0.len() - 1].0);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.fuel(mid, cost) <= self.fuel(mid + 1, cost) {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        self.alignment(low, cost)
+    }
+}
+
+// Finds the position requiring the least fuel to align all crabs.
+// Ties are resolved in favor of the leftmost position.
+// `None` without crabs or if all of them weigh nothing.
+fn align(crabs: &[Crab], cost: &FuelCost) -> Option<Alignment> {
+    if crabs.iter().all(|crab| crab.weight == 0) {
+        return None;
+    }
+    let buckets = Buckets::new(crabs);
+    Some(match cost {
+        FuelCost::Linear => buckets.alignment(buckets.median(), cost),
+        FuelCost::Triangular => buckets.near_mean(cost),
+        FuelCost::Convex(_) => buckets.ternary_search(cost),
+    })
+}
+
+// The Manhattan distance separates into the two axes,
+// each of which is solved by a weighted median.
+fn align_manhattan(crabs: &[Crab<(i64, i64)>]) -> Option<Alignment<(i64, i64)>> {
+    let project = |axis: fn((i64, i64)) -> i64| -> Vec<Crab> {
+        crabs
+            .iter()
+            .map(|crab| Crab {
+                position: axis(crab.position),
+                weight: crab.weight,
+            })
+            .collect()
+    };
+    let x = align(&project(|(x, _)| x), &FuelCost::Linear)?;
+    let y = align(&project(|(_, y)| y), &FuelCost::Linear)?;
+    Some(Alignment {
+        position: (x.position, y.position),
+        fuel: x.fuel + y.fuel,
+    })
+}
+
+fn euclidean_fuel(crabs: &[Crab<(i64, i64)>], (x, y): (f64, f64)) -> f64 {
+    crabs
+        .iter()
+        .map(|crab| {
+            let (cx, cy) = crab.position;
+            crab.weight as f64 * (cx as f64 - x).hypot(cy as f64 - y)
+        })
+        .sum()
+}
+
+// Weiszfeld's algorithm for the geometric median with the modification by
+// Vardi and Zhang for iterates that coincide with a crab.
+fn align_euclidean(crabs: &[Crab<(i64, i64)>]) -> Option<Alignment<(f64, f64), f64>> {
+    if crabs.iter().all(|crab| crab.weight == 0) {
+        return None;
+    }
+    let total: f64 = crabs.iter().map(|crab| crab.weight as f64).sum();
+    let mut current = crabs.iter().fold((0.0, 0.0), |(x, y), crab| {
+        let weight = crab.weight as f64 / total;
+        (
+            x + weight * crab.position.0 as f64,
+            y + weight * crab.position.1 as f64,
+        )
+    });
+    for _ in 0..10_000 {
+        let (mut x, mut y, mut denominator) = (0.0, 0.0, 0.0);
+        let (mut pull_x, mut pull_y, mut coinciding) = (0.0, 0.0, 0.0);
+        for crab in crabs {
+            let (cx, cy) = (crab.position.0 as f64, crab.position.1 as f64);
+            let distance = (cx - current.0).hypot(cy - current.1);
+            if distance < 1e-12 {
+                coinciding += crab.weight as f64;
+                continue;
+            }
+            let weight = crab.weight as f64 / distance;
+            x += weight * cx;
+            y += weight * cy;
+            denominator += weight;
+            pull_x += weight * (cx - current.0);
+            pull_y += weight * (cy - current.1);
+        }
+        let pull = pull_x.hypot(pull_y);
+        if denominator == 0.0 || pull <= coinciding {
+            break;
+        }
+        let share = coinciding / pull;
+        let next = (
+            (1.0 - share) * x / denominator + share * current.0,
+            (1.0 - share) * y / denominator + share * current.1,
+        );
+        let step = (next.0 - current.0).hypot(next.1 - current.1);
+        current = next;
+        if step < 1e-12 * (1.0 + current.0.hypot(current.1)) {
+            break;
+        }
+    }
+    Some(Alignment {
+        position: current,
+        fuel: euclidean_fuel(crabs, current),
+    })
+}
+
+impl<P: Debug, F: Display> Display for Alignment<P, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (position {:?})", self.fuel, self.position)
     }
-    
-    min_fuel
 }
 
-fn parse_input<R: BufRead>(reader: &mut R) -> Result<Vec<u64>, Box<dyn Error>> {
+// A crab is given as `x` or `x;y`, optionally followed by `@weight`.
+fn parse_crab(s: &str) -> Result<Crab<Vec<i64>>, Box<dyn Error>> {
+    let (position, weight) = match s.split_once('@') {
+        Some((position, weight)) => (position, weight.trim().parse()?),
+        None => (s, 1),
+    };
+    if weight == 0 {
+        return Err(format!("crab '{}' has no weight", s.trim()).into());
+    }
+    let position = position
+        .split(';')
+        .map(|x| x.trim().parse())
+        .collect::<Result<Vec<i64>, _>>()?;
+    Ok(Crab { position, weight })
+}
+
+fn parse_input<R: BufRead>(reader: &mut R) -> Result<Vec<Crab<Vec<i64>>>, Box<dyn Error>> {
     let mut buf = String::new();
     reader.read_line(&mut buf)?;
-    Ok(buf.split(',').map(|x| u64::from_str_radix(x.trim(), 10)).collect::<Result<Vec<u64>, ParseIntError>>()?)
+    buf.split(',').map(parse_crab).collect()
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let quadratic = env::args().skip(1).any(|arg| arg == "--quadratic");
     let stdin = io::stdin();
     let input = parse_input(&mut stdin.lock())?;
-    println!("Part 1 (constant): {}", least_fuel_constant(&input));
-    println!("Part 2 (linear): {}", least_fuel_linear(&input));
+    let dimensions = input.first().ok_or("no crabs")?.position.len();
+    if input.iter().any(|crab| crab.position.len() != dimensions) {
+        return Err("crabs with different dimensions".into());
+    }
+    match dimensions {
+        1 => {
+            let crabs: Vec<Crab> = input
+                .iter()
+                .map(|crab| Crab {
+                    position: crab.position[0],
+                    weight: crab.weight,
+                })
+                .collect();
+            let part1 = align(&crabs, &FuelCost::Linear).ok_or("no crabs")?;
+            println!("Part 1 (constant): {}", part1);
+            let part2 = align(&crabs, &FuelCost::Triangular).ok_or("no crabs")?;
+            println!("Part 2 (linear): {}", part2);
+            if quadratic {
+                let cost = FuelCost::Convex(Box::new(|distance| distance * distance));
+                println!("Quadratic: {}", align(&crabs, &cost).ok_or("no crabs")?);
+            }
+        }
+        2 => {
+            let crabs: Vec<Crab<(i64, i64)>> = input
+                .iter()
+                .map(|crab| Crab {
+                    position: (crab.position[0], crab.position[1]),
+                    weight: crab.weight,
+                })
+                .collect();
+            println!("Manhattan: {}", align_manhattan(&crabs).ok_or("no crabs")?);
+            println!("Euclidean: {}", align_euclidean(&crabs).ok_or("no crabs")?);
+        }
+        _ => return Err(format!("unsupported number of dimensions {}", dimensions).into()),
+    }
     Ok(())
 }
 
@@ -72,21 +288,253 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod tests {
     use super::*;
 
+    fn unweighted(positions: &[i64]) -> Vec<Crab> {
+        positions
+            .iter()
+            .map(|&position| Crab::from(position))
+            .collect()
+    }
+
+    fn brute_force(crabs: &[Crab], cost: &FuelCost) -> Alignment {
+        let buckets = Buckets::new(crabs);
+        let (min, max) = (buckets.0[0].0, buckets.0[buckets.0.len() - 1].0);
+        (min..=max)
+            .map(|position| buckets.alignment(position, cost))
+            .min_by_key(|alignment| alignment.fuel)
+            .unwrap()
+    }
+
     #[test]
     fn test_least_fuel() {
-        let test_input = vec![16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
-        assert_eq!(least_fuel_constant(&test_input), 37);
+        let test_input = unweighted(&[16, 1, 2, 0, 4, 2, 7, 1, 2, 14]);
+        assert_eq!(
+            align(&test_input, &FuelCost::Linear),
+            Some(Alignment {
+                position: 2,
+                fuel: 37
+            })
+        );
     }
-    
+
     #[test]
     fn test_least_fuel2() {
-        let test_input = vec![16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
-        assert_eq!(least_fuel_linear(&test_input), 168);
+        let test_input = unweighted(&[16, 1, 2, 0, 4, 2, 7, 1, 2, 14]);
+        assert_eq!(
+            align(&test_input, &FuelCost::Triangular),
+            Some(Alignment {
+                position: 5,
+                fuel: 168
+            })
+        );
+    }
+
+    #[test]
+    fn test_convex_cost() {
+        let test_input = unweighted(&[16, 1, 2, 0, 4, 2, 7, 1, 2, 14]);
+        let triangular = FuelCost::Convex(Box::new(|x| x * (x + 1) / 2));
+        assert_eq!(
+            align(&test_input, &triangular),
+            align(&test_input, &FuelCost::Triangular)
+        );
+        let quadratic = FuelCost::Convex(Box::new(|x| x * x));
+        assert_eq!(
+            align(&test_input, &quadratic),
+            Some(brute_force(&test_input, &quadratic))
+        );
+        assert_eq!(align(&[], &quadratic), None);
     }
-    
+
+    #[test]
+    fn test_matches_brute_force() {
+        let costs = vec![
+            FuelCost::Linear,
+            FuelCost::Triangular,
+            FuelCost::Convex(Box::new(|x| x)),
+            FuelCost::Convex(Box::new(|x| x * x * x)),
+            // Flat regions lead to ties.
+            FuelCost::Convex(Box::new(|x| x.saturating_sub(3))),
+        ];
+        let mut state = 0x2545f4914f6cdd1du64;
+        for size in 1..40 {
+            let mut next = || {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state
+            };
+            let positions: Vec<Crab> = (0..size)
+                .map(|_| Crab {
+                    position: (next() % 50) as i64 - 25,
+                    weight: next() % 5 + 1,
+                })
+                .collect();
+            for cost in &costs {
+                let expected = brute_force(&positions, cost);
+                let alignment = align(&positions, cost).unwrap();
+                assert_eq!(alignment.fuel, expected.fuel, "{:?}", positions);
+                if let FuelCost::Convex(_) = cost {
+                    assert_eq!(alignment.position, expected.position);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_wide_range() {
+        let positions = unweighted(&[0, 1_000_000_000, 3, 999_999_999]);
+        assert_eq!(
+            align(&positions, &FuelCost::Linear).unwrap().fuel,
+            1_999_999_996
+        );
+        assert_eq!(
+            align(&positions, &FuelCost::Triangular).unwrap().position,
+            500_000_000
+        );
+    }
+
+    #[test]
+    fn test_weights() {
+        // The heavy crab does not move.
+        let crabs = vec![
+            Crab::from(-5),
+            Crab {
+                position: 3,
+                weight: 4,
+            },
+            Crab::from(10),
+            Crab::from(12),
+        ];
+        assert_eq!(
+            align(&crabs, &FuelCost::Linear),
+            Some(Alignment {
+                position: 3,
+                fuel: 8 + 7 + 9
+            })
+        );
+        assert_eq!(
+            align(&crabs, &FuelCost::Triangular),
+            Some(brute_force(&crabs, &FuelCost::Triangular))
+        );
+    }
+
+    #[test]
+    fn test_manhattan() {
+        let crabs = vec![
+            Crab::from((0, 0)),
+            Crab::from((4, -3)),
+            Crab {
+                position: (1, 5),
+                weight: 2,
+            },
+        ];
+        let alignment = align_manhattan(&crabs).unwrap();
+        assert_eq!(alignment.position, (1, 0));
+        let brute_force = (-3..=5)
+            .flat_map(|x| (-3..=5).map(move |y| (x, y)))
+            .map(|(x, y): (i64, i64)| {
+                crabs
+                    .iter()
+                    .map(|crab| {
+                        crab.weight
+                            * ((crab.position.0 - x).unsigned_abs()
+                                + (crab.position.1 - y).unsigned_abs())
+                    })
+                    .sum::<u64>()
+            })
+            .min()
+            .unwrap();
+        assert_eq!(alignment.fuel, brute_force);
+        assert_eq!(align_manhattan(&[]), None);
+    }
+
+    #[test]
+    fn test_euclidean() {
+        let square: Vec<_> = [(-1, -1), (1, -1), (1, 1), (-1, 1)]
+            .iter()
+            .map(|&position| Crab::from(position))
+            .collect();
+        let alignment = align_euclidean(&square).unwrap();
+        assert!(alignment.position.0.abs() < 1e-6 && alignment.position.1.abs() < 1e-6);
+        assert!((alignment.fuel - 4.0 * 2f64.sqrt()).abs() < 1e-6);
+
+        // A crab outweighing all others is the meeting point.
+        let crabs = vec![
+            Crab::from((0, 0)),
+            Crab::from((10, 0)),
+            Crab {
+                position: (3, 7),
+                weight: 2,
+            },
+        ];
+        let alignment = align_euclidean(&crabs).unwrap();
+        assert!((alignment.position.0 - 3.0).abs() < 1e-9);
+        assert!((alignment.position.1 - 7.0).abs() < 1e-9);
+
+        let crabs = vec![
+            Crab::from((0, 0)),
+            Crab::from((10, 0)),
+            Crab::from((3, 7)),
+            Crab {
+                position: (-4, 2),
+                weight: 3,
+            },
+        ];
+        let alignment = align_euclidean(&crabs).unwrap();
+        for i in -100..=100 {
+            for j in -100..=100 {
+                let point = (
+                    alignment.position.0 + i as f64 * 0.01,
+                    alignment.position.1 + j as f64 * 0.01,
+                );
+                assert!(alignment.fuel <= euclidean_fuel(&crabs, point) + 1e-9);
+            }
+        }
+    }
+
     #[test]
     fn test_parse_input() {
         let mut input: &[u8] = "16,1,2,0,4,2,7,1,2,14\n".as_bytes();
-        assert_eq!(parse_input(&mut input).unwrap(), vec![16, 1, 2, 0, 4, 2, 7, 1, 2, 14]);
+        assert_eq!(
+            parse_input(&mut input).unwrap(),
+            [16, 1, 2, 0, 4, 2, 7, 1, 2, 14]
+                .iter()
+                .map(|&position| Crab::from(vec![position]))
+                .collect::<Vec<_>>()
+        );
+        let mut input: &[u8] = "-3@2,4;-1\n".as_bytes();
+        assert_eq!(
+            parse_input(&mut input).unwrap(),
+            vec![
+                Crab {
+                    position: vec![-3],
+                    weight: 2
+                },
+                Crab::from(vec![4, -1])
+            ]
+        );
+        let mut input: &[u8] = "1@x\n".as_bytes();
+        assert!(parse_input(&mut input).is_err());
+        let mut input: &[u8] = "1@0,2@0\n".as_bytes();
+        assert!(parse_input(&mut input).is_err());
+    }
+
+    #[test]
+    fn test_zero_weights() {
+        fn weightless<P>(position: P) -> Crab<P> {
+            Crab {
+                position,
+                weight: 0,
+            }
+        }
+        assert_eq!(
+            align(&[weightless(1), weightless(2)], &FuelCost::Triangular),
+            None
+        );
+        assert_eq!(align(&[], &FuelCost::Linear), None);
+        assert_eq!(align_manhattan(&[weightless((1, 2))]), None);
+        assert_eq!(
+            align_euclidean(&[weightless((1, 2)), weightless((3, 4))]),
+            None
+        );
     }
 }
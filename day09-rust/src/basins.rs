@@ -0,0 +1,209 @@
+use crate::Pos;
+
+// Union-find over the cells of the heightmap.
+struct DisjointSets {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+            size: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Basin {
+    pub area: u64,
+    // The first cell with the lowest height in row-major order.
+    pub low_point: Pos,
+    pub lowest: u8,
+    pub highest: u8,
+    // Inclusive upper left and lower right corner.
+    pub bounding_box: (Pos, Pos),
+}
+
+impl Basin {
+    fn new(pos: Pos, height: u8) -> Self {
+        Self {
+            area: 0,
+            low_point: pos,
+            lowest: height,
+            highest: height,
+            bounding_box: (pos, pos),
+        }
+    }
+
+    fn add(&mut self, pos: Pos, height: u8) {
+        self.area += 1;
+        if height < self.lowest {
+            self.lowest = height;
+            self.low_point = pos;
+        }
+        self.highest = self.highest.max(height);
+        let (min, max) = &mut self.bounding_box;
+        *min = (min.0.min(pos.0), min.1.min(pos.1));
+        *max = (max.0.max(pos.0), max.1.max(pos.1));
+    }
+}
+
+// Every cell labeled with the id of its basin (`None` for ridges).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasinMap {
+    pub labels: Vec<Vec<Option<usize>>>,
+    pub basins: Vec<Basin>,
+}
+
+impl BasinMap {
+    pub fn largest_product(&self, n: usize) -> u64 {
+        let mut areas: Vec<u64> = self.basins.iter().map(|basin| basin.area).collect();
+        areas.sort_unstable_by(|a, b| b.cmp(a));
+        areas.into_iter().take(n).product()
+    }
+}
+
+pub fn is_ridge(height: u8) -> bool {
+    height >= 9
+}
+
+// A single scan merges each cell with its upper and left neighbor.
+// Basin ids are assigned in row-major order of the basins' first cells.
+pub fn label_basins<Map: AsRef<[Row]>, Row: AsRef<[u8]>>(map: Map) -> BasinMap {
+    let map = map.as_ref();
+    let width = map.first().map_or(0, |row| row.as_ref().len());
+    let mut sets = DisjointSets::new(map.len() * width);
+    for (i, row) in map.iter().enumerate() {
+        for (j, &height) in row.as_ref().iter().enumerate() {
+            if is_ridge(height) {
+                continue;
+            }
+            if i > 0 && !is_ridge(map[i - 1].as_ref()[j]) {
+                sets.union(i * width + j, (i - 1) * width + j);
+            }
+            if j > 0 && !is_ridge(row.as_ref()[j - 1]) {
+                sets.union(i * width + j, i * width + j - 1);
+            }
+        }
+    }
+
+    let mut ids = vec![None; map.len() * width];
+    let mut basins: Vec<Basin> = vec![];
+    let labels = map
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.as_ref()
+                .iter()
+                .enumerate()
+                .map(|(j, &height)| {
+                    if is_ridge(height) {
+                        return None;
+                    }
+                    let root = sets.find(i * width + j);
+                    let id = *ids[root].get_or_insert_with(|| {
+                        basins.push(Basin::new((i, j), height));
+                        basins.len() - 1
+                    });
+                    basins[id].add((i, j), height);
+                    Some(id)
+                })
+                .collect()
+        })
+        .collect();
+    BasinMap { labels, basins }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TEST_MAP: [[u8; 10]; 5] = [
+        [2, 1, 9, 9, 9, 4, 3, 2, 1, 0],
+        [3, 9, 8, 7, 8, 9, 4, 9, 2, 1],
+        [9, 8, 5, 6, 7, 8, 9, 8, 9, 2],
+        [8, 7, 6, 7, 8, 9, 6, 7, 8, 9],
+        [9, 8, 9, 9, 9, 6, 5, 6, 7, 8],
+    ];
+
+    #[test]
+    fn test_label_basins() {
+        let basin_map = label_basins(TEST_MAP);
+        assert_eq!(
+            basin_map.basins,
+            vec![
+                Basin {
+                    area: 3,
+                    low_point: (0, 1),
+                    lowest: 1,
+                    highest: 3,
+                    bounding_box: ((0, 0), (1, 1)),
+                },
+                Basin {
+                    area: 9,
+                    low_point: (0, 9),
+                    lowest: 0,
+                    highest: 4,
+                    bounding_box: ((0, 5), (2, 9)),
+                },
+                Basin {
+                    area: 14,
+                    low_point: (2, 2),
+                    lowest: 5,
+                    highest: 8,
+                    bounding_box: ((1, 0), (4, 5)),
+                },
+                Basin {
+                    area: 9,
+                    low_point: (4, 6),
+                    lowest: 5,
+                    highest: 8,
+                    bounding_box: ((2, 5), (4, 9)),
+                },
+            ]
+        );
+        assert_eq!(basin_map.labels[0][2], None);
+        assert_eq!(basin_map.labels[3][0], Some(2));
+        assert_eq!(basin_map.largest_product(3), 1134);
+    }
+
+    #[test]
+    fn test_scanline_merges() {
+        // The two arms are only joined in the last row.
+        let map = [[1, 9, 1], [2, 9, 2], [3, 4, 3]];
+        let basin_map = label_basins(map);
+        assert_eq!(basin_map.basins.len(), 1);
+        assert_eq!(basin_map.basins[0].area, 7);
+        assert_eq!(basin_map.labels[0][0], basin_map.labels[0][2]);
+    }
+
+    #[test]
+    fn test_empty_map() {
+        let map: [[u8; 0]; 0] = [];
+        let basin_map = label_basins(map);
+        assert!(basin_map.basins.is_empty());
+        assert_eq!(basin_map.largest_product(3), 1);
+    }
+}
@@ -1,8 +1,13 @@
-use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashSet, VecDeque};
+mod basins;
+mod render;
+
+use std::env;
 use std::error::Error;
-use std::io::{self, BufRead};
-use std::iter::{FromIterator, Iterator};
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter};
+use std::iter::Iterator;
+
+use basins::label_basins;
 
 type Pos = (usize, usize);
 
@@ -39,7 +44,7 @@ impl Iterator for NeighborIterator {
                 return next_item;
             }
         }
-        return None;
+        None
     }
 }
 
@@ -54,52 +59,12 @@ fn find_low_points<Map: AsRef<[Row]>, Row: AsRef<[u8]>>(map: Map) -> Vec<u8> {
                 if iter.all(|pos| height < map[pos.0].as_ref()[pos.1]) {
                     return Some(height);
                 }
-                return None;
+                None
             })
         })
         .collect()
 }
 
-fn determine_basin_area<Map: AsRef<[Row]>, Row: AsRef<[u8]>>(map: Map) -> u64 {
-    let map = map.as_ref();
-    let mut visited = HashSet::<Pos>::new();
-    let mut basins: BinaryHeap<Reverse<u64>> = BinaryHeap::with_capacity(4);
-    for (i, row) in map.iter().enumerate() {
-        let row = row.as_ref();
-        for (j, &height) in row.iter().enumerate() {
-            if height >= 9 || visited.contains(&(i, j)) {
-                continue;
-            }
-
-            visited.insert((i, j));
-            let mut area = 1;
-
-            let upper_bounds = (map.len() - 1, row.len() - 1);
-            let mut to_visit = VecDeque::from_iter(NeighborIterator::new((i, j), upper_bounds));
-            while let Some(visited_pos) = to_visit.pop_front() {
-                if visited.contains(&visited_pos) || map[visited_pos.0].as_ref()[visited_pos.1] >= 9
-                {
-                    continue;
-                }
-
-                visited.insert(visited_pos);
-                area += 1;
-
-                for neighbor in NeighborIterator::new(visited_pos, upper_bounds) {
-                    to_visit.push_back(neighbor);
-                }
-            }
-
-            basins.push(Reverse(area));
-            while basins.len() > 3 {
-                basins.pop();
-            }
-        }
-    }
-
-    basins.into_iter().take(3).map(|x| x.0).product()
-}
-
 fn calculate_risk(low_points: &[u8]) -> u64 {
     low_points.iter().map(|&x| x as u64).sum::<u64>() + low_points.len() as u64
 }
@@ -118,14 +83,73 @@ fn parse_input<R: BufRead>(reader: &mut R) -> Result<Vec<Vec<u8>>, Box<dyn Error
         .collect()
 }
 
-fn main() {
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    basins: bool,
+    ansi: bool,
+    render: Option<String>,
+    scale: usize,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options {
+        basins: false,
+        ansi: false,
+        render: None,
+        scale: 4,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--basins" => options.basins = true,
+            "--ansi" => options.ansi = true,
+            "--render" => options.render = Some(args.next().ok_or("missing image path")?),
+            "--scale" => {
+                options.scale = args.next().ok_or("missing scale")?.parse()?;
+                if options.scale == 0 {
+                    return Err("scale must be positive".into());
+                }
+            }
+            _ => return Err(format!("unknown argument {}", arg).into()),
+        }
+    }
+    Ok(options)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = parse_args(env::args().skip(1))?;
     let stdin = io::stdin();
-    let map = parse_input(&mut stdin.lock()).unwrap();
+    let map = parse_input(&mut stdin.lock())?;
     println!("Total risk: {}", calculate_risk(&find_low_points(&map)));
+    let basin_map = label_basins(&map);
     println!(
         "Product of three largest basins: {}",
-        determine_basin_area(&map)
+        basin_map.largest_product(3)
     );
+    if options.basins {
+        for (id, basin) in basin_map.basins.iter().enumerate() {
+            let ((top, left), (bottom, right)) = basin.bounding_box;
+            println!(
+                "Basin {}: area {}, low point {:?}, heights {}-{}, rows {}-{}, columns {}-{}",
+                id,
+                basin.area,
+                basin.low_point,
+                basin.lowest,
+                basin.highest,
+                top,
+                bottom,
+                left,
+                right
+            );
+        }
+    }
+    if options.ansi {
+        render::render_ansi(&mut io::stdout().lock(), &map, &basin_map)?;
+    }
+    if let Some(path) = options.render {
+        let mut writer = BufWriter::new(File::create(path)?);
+        render::render_ppm(&mut writer, &map, &basin_map, options.scale)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -176,7 +200,9 @@ mod tests {
 
     #[test]
     fn test_find_low_points() {
-        assert_eq!(find_low_points(TEST_MAP).sort(), vec![1, 0, 5, 5].sort());
+        let mut low_points = find_low_points(TEST_MAP);
+        low_points.sort_unstable();
+        assert_eq!(low_points, [0, 1, 5, 5]);
     }
 
     #[test]
@@ -185,8 +211,26 @@ mod tests {
     }
 
     #[test]
-    fn test_determine_basin_area() {
-        assert_eq!(determine_basin_area(TEST_MAP), 1134);
+    fn test_largest_basins() {
+        assert_eq!(label_basins(TEST_MAP).largest_product(3), 1134);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = ["--ansi", "--render", "basins.ppm", "--scale", "2"]
+            .iter()
+            .map(|arg| arg.to_string());
+        assert_eq!(
+            parse_args(args).unwrap(),
+            Options {
+                basins: false,
+                ansi: true,
+                render: Some("basins.ppm".into()),
+                scale: 2,
+            }
+        );
+        assert!(parse_args(["--scale", "0"].iter().map(|arg| arg.to_string())).is_err());
+        assert!(parse_args(["--render"].iter().map(|arg| arg.to_string())).is_err());
     }
 
     #[test]
@@ -0,0 +1,138 @@
+use std::io::{self, Write};
+
+use crate::basins::BasinMap;
+
+type Color = [u8; 3];
+
+const RIDGE: Color = [32, 32, 32];
+const LOW_POINT: Color = [255, 255, 255];
+
+// Spreads the hues of consecutive basins by the golden angle.
+fn basin_color(id: usize) -> Color {
+    let hue = (id as f64 * 0.618_033_988_749_895).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u8 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [r, g, b].map(|c| (c * 255.0) as u8)
+}
+
+// Lower cells are drawn brighter.
+fn shade(color: Color, height: u8) -> Color {
+    let brightness = 1.0 - 0.6 * height.min(9) as f64 / 9.0;
+    color.map(|c| (c as f64 * brightness) as u8)
+}
+
+fn cell_color<Map: AsRef<[Row]>, Row: AsRef<[u8]>>(
+    map: &Map,
+    basin_map: &BasinMap,
+    (i, j): (usize, usize),
+) -> Color {
+    match basin_map.labels[i][j] {
+        None => RIDGE,
+        Some(id) if basin_map.basins[id].low_point == (i, j) => LOW_POINT,
+        Some(id) => shade(basin_color(id), map.as_ref()[i].as_ref()[j]),
+    }
+}
+
+// Writes a binary PPM image with `scale` pixels per cell.
+pub fn render_ppm<W: Write, Map: AsRef<[Row]>, Row: AsRef<[u8]>>(
+    writer: &mut W,
+    map: Map,
+    basin_map: &BasinMap,
+    scale: usize,
+) -> io::Result<()> {
+    let height = basin_map.labels.len();
+    let width = basin_map.labels.first().map_or(0, |row| row.len());
+    write!(writer, "P6\n{} {}\n255\n", width * scale, height * scale)?;
+    for i in 0..height {
+        let row: Vec<u8> = (0..width)
+            .flat_map(|j| {
+                let color = cell_color(&map, basin_map, (i, j));
+                std::iter::repeat_n(color, scale).flatten()
+            })
+            .collect();
+        for _ in 0..scale {
+            writer.write_all(&row)?;
+        }
+    }
+    Ok(())
+}
+
+// Writes the heights with 24-bit ANSI background colors.
+pub fn render_ansi<W: Write, Map: AsRef<[Row]>, Row: AsRef<[u8]>>(
+    writer: &mut W,
+    map: Map,
+    basin_map: &BasinMap,
+) -> io::Result<()> {
+    for (i, row) in map.as_ref().iter().enumerate() {
+        for (j, height) in row.as_ref().iter().enumerate() {
+            let [r, g, b] = cell_color(&map, basin_map, (i, j));
+            let foreground = if basin_map.labels[i][j].is_none() {
+                "37"
+            } else {
+                "30"
+            };
+            write!(
+                writer,
+                "\x1b[{};48;2;{};{};{}m{}",
+                foreground, r, g, b, height
+            )?;
+        }
+        writeln!(writer, "\x1b[0m")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basins::label_basins;
+
+    static TEST_MAP: [[u8; 3]; 2] = [[1, 9, 0], [2, 9, 3]];
+
+    #[test]
+    fn test_render_ppm() {
+        let basin_map = label_basins(TEST_MAP);
+        let mut buf = vec![];
+        render_ppm(&mut buf, TEST_MAP, &basin_map, 2).unwrap();
+        let header = b"P6\n6 4\n255\n";
+        assert_eq!(&buf[..header.len()], header);
+        let pixels = &buf[header.len()..];
+        assert_eq!(pixels.len(), 6 * 4 * 3);
+        let pixel = |x: usize, y: usize| &pixels[3 * (y * 6 + x)..3 * (y * 6 + x + 1)];
+        assert_eq!(pixel(1, 1), LOW_POINT);
+        assert_eq!(pixel(4, 0), LOW_POINT);
+        assert_eq!(pixel(2, 3), RIDGE);
+        assert_eq!(pixel(0, 2), shade(basin_color(0), 2));
+        assert_eq!(pixel(5, 3), shade(basin_color(1), 3));
+    }
+
+    #[test]
+    fn test_render_ansi() {
+        let basin_map = label_basins(TEST_MAP);
+        let mut buf = vec![];
+        render_ansi(&mut buf, TEST_MAP, &basin_map).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("\x1b[30;48;2;255;255;255m1"));
+        assert!(lines[0].contains("\x1b[37;48;2;32;32;32m9"));
+        assert!(lines[1].ends_with("3\x1b[0m"));
+    }
+
+    #[test]
+    fn test_basin_colors_differ() {
+        let colors: Vec<Color> = (0..8).map(basin_color).collect();
+        for (i, a) in colors.iter().enumerate() {
+            for b in &colors[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BracketPair {
+    pub opening: char,
+    pub closing: char,
+    pub invalid_score: u64,
+    pub incomplete_score: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidPairError(String);
+
+impl Display for InvalidPairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid bracket pair '{}', expected OC or OC:INVALID:INCOMPLETE",
+            self.0
+        )
+    }
+}
+
+impl Error for InvalidPairError {}
+
+// `OC` or `OC:INVALID:INCOMPLETE` with the opening and closing character.
+impl FromStr for BracketPair {
+    type Err = InvalidPairError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidPairError(s.into());
+        let mut parts = s.split(':');
+        let mut chars = parts.next().ok_or_else(error)?.chars();
+        let (opening, closing) = match (chars.next(), chars.next(), chars.next()) {
+            (Some(opening), Some(closing), None) if opening != closing => (opening, closing),
+            _ => return Err(error()),
+        };
+        let (invalid_score, incomplete_score) = match (parts.next(), parts.next(), parts.next()) {
+            (None, _, _) => (0, 0),
+            (Some(invalid), Some(incomplete), None) => (
+                invalid.parse().map_err(|_| error())?,
+                incomplete.parse().map_err(|_| error())?,
+            ),
+            _ => return Err(error()),
+        };
+        Ok(Self {
+            opening,
+            closing,
+            invalid_score,
+            incomplete_score,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ignore {
+    Whitespace,
+    Alphanumeric,
+    // Any character not belonging to a bracket pair.
+    NonBrackets,
+    Chars(String),
+}
+
+// A string literal or comment in which brackets are not checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: String,
+    pub end: String,
+    pub escape: Option<char>,
+    // Regions with at most this many (escaped) characters, e.g. `'a'`.
+    // Without the end in time, the start is an ordinary character.
+    pub max_len: Option<usize>,
+}
+
+impl Region {
+    pub fn new(start: &str, end: &str) -> Self {
+        Self {
+            start: start.into(),
+            end: end.into(),
+            escape: None,
+            max_len: None,
+        }
+    }
+
+    pub fn with_escape(mut self, escape: char) -> Self {
+        self.escape = Some(escape);
+        self
+    }
+
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    // The index after the region if one starts at `chars[i]`. Unterminated
+    // regions extend to the end unless their length is limited. A braced
+    // escape like `\u{7b}` counts as a single character.
+    fn end_at(&self, chars: &[char], i: usize) -> Option<usize> {
+        if !starts_with(&chars[i..], &self.start) {
+            return None;
+        }
+        let mut i = i + self.start.chars().count();
+        let mut len = 0;
+        while !starts_with(&chars[i.min(chars.len())..], &self.end) {
+            if i >= chars.len() {
+                return self.max_len.is_none().then_some(chars.len());
+            }
+            if self.max_len == Some(len) {
+                return None;
+            }
+            i += if Some(chars[i]) == self.escape {
+                escape_len(&chars[i..])
+            } else {
+                1
+            };
+            len += 1;
+        }
+        Some((i + self.end.chars().count()).min(chars.len()))
+    }
+}
+
+// The length of the escape sequence at the start of `chars`, where a brace
+// right after the escaped character opens up to six more, e.g. `\u{10ffff}`.
+fn escape_len(chars: &[char]) -> usize {
+    if chars.get(2) != Some(&'{') {
+        return 2;
+    }
+    chars[..chars.len().min(10)]
+        .iter()
+        .position(|&c| c == '}')
+        .map_or(2, |close| close + 1)
+}
+
+fn starts_with(chars: &[char], pattern: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    !pattern.is_empty() && chars.starts_with(&pattern)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BracketSet {
+    pub pairs: Vec<BracketPair>,
+    pub ignore: Vec<Ignore>,
+    pub regions: Vec<Region>,
+    pub incomplete_multiplier: u64,
+}
+
+impl Default for BracketSet {
+    fn default() -> Self {
+        let pair = |opening, closing, invalid_score, incomplete_score| BracketPair {
+            opening,
+            closing,
+            invalid_score,
+            incomplete_score,
+        };
+        Self::new(vec![
+            pair('(', ')', 3, 1),
+            pair('[', ']', 57, 2),
+            pair('{', '}', 1197, 3),
+            pair('<', '>', 25137, 4),
+        ])
+    }
+}
+
+impl BracketSet {
+    pub fn new(pairs: Vec<BracketPair>) -> Self {
+        Self {
+            pairs,
+            ignore: vec![],
+            regions: vec![],
+            incomplete_multiplier: 5,
+        }
+    }
+
+    // Brackets without the angle brackets used as operators, skipping
+    // everything else as well as string and character literals and comments.
+    // A quote without a closing one right after the next character starts a
+    // lifetime instead of a character literal. Raw strings are recognized
+    // with up to three `#`.
+    pub fn source_code() -> Self {
+        let mut brackets = Self::default();
+        brackets.pairs.retain(|pair| pair.opening != '<');
+        brackets = brackets.with_ignore(Ignore::NonBrackets);
+        for hashes in ["###", "##", "#", ""] {
+            brackets = brackets.with_region(Region::new(
+                &format!("r{}\"", hashes),
+                &format!("\"{}", hashes),
+            ));
+        }
+        brackets
+            .with_region(Region::new("\"", "\"").with_escape('\\'))
+            .with_region(Region::new("'", "'").with_escape('\\').with_max_len(1))
+            .with_region(Region::new("//", "\n"))
+            .with_region(Region::new("/*", "*/"))
+    }
+
+    pub fn with_ignore(mut self, ignore: Ignore) -> Self {
+        self.ignore.push(ignore);
+        self
+    }
+
+    pub fn with_region(mut self, region: Region) -> Self {
+        self.regions.push(region);
+        self
+    }
+
+    pub fn with_incomplete_multiplier(mut self, incomplete_multiplier: u64) -> Self {
+        self.incomplete_multiplier = incomplete_multiplier;
+        self
+    }
+
+    pub fn closing(&self, opening: char) -> Option<char> {
+        self.pairs
+            .iter()
+            .find(|pair| pair.opening == opening)
+            .map(|pair| pair.closing)
+    }
+
+    pub fn opening(&self, closing: char) -> Option<char> {
+        self.pairs
+            .iter()
+            .find(|pair| pair.closing == closing)
+            .map(|pair| pair.opening)
+    }
+
+    pub fn invalid_score(&self, c: char) -> u64 {
+        self.pairs
+            .iter()
+            .find(|pair| pair.closing == c)
+            .map_or(0, |pair| pair.invalid_score)
+    }
+
+    pub fn incomplete_score(&self, c: char) -> u64 {
+        self.pairs
+            .iter()
+            .find(|pair| pair.closing == c)
+            .map_or(0, |pair| pair.incomplete_score)
+    }
+
+    fn is_ignored(&self, c: char) -> bool {
+        self.ignore.iter().any(|ignore| match ignore {
+            Ignore::Whitespace => c.is_whitespace(),
+            Ignore::Alphanumeric => c.is_alphanumeric(),
+            Ignore::NonBrackets => self.closing(c).is_none() && self.opening(c).is_none(),
+            Ignore::Chars(chars) => chars.contains(c),
+        })
+    }
+
+    // The characters to check with their positions, i.e. all characters
+    // that are neither ignored nor within a region.
+    pub fn tokens(&self, text: &str) -> Vec<(usize, char)> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut tokens = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            match self
+                .regions
+                .iter()
+                .find_map(|region| region.end_at(&chars, i))
+            {
+                Some(end) => i = end,
+                None => {
+                    if !self.is_ignored(chars[i]) {
+                        tokens.push((i, chars[i]));
+                    }
+                    i += 1;
+                }
+            }
+        }
+        tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pair() {
+        assert_eq!(
+            "():3:1".parse(),
+            Ok(BracketPair {
+                opening: '(',
+                closing: ')',
+                invalid_score: 3,
+                incomplete_score: 1,
+            })
+        );
+        assert_eq!(
+            "«»".parse::<BracketPair>().map(|pair| pair.closing),
+            Ok('»')
+        );
+        assert!("(".parse::<BracketPair>().is_err());
+        assert!("((".parse::<BracketPair>().is_err());
+        assert!("():3".parse::<BracketPair>().is_err());
+        assert!("():a:1".parse::<BracketPair>().is_err());
+    }
+
+    #[test]
+    fn test_tokens() {
+        let brackets = BracketSet::default()
+            .with_ignore(Ignore::Whitespace)
+            .with_ignore(Ignore::Chars("ab".into()));
+        assert_eq!(
+            brackets.tokens("( a b\t]c"),
+            vec![(0, '('), (6, ']'), (7, 'c')]
+        );
+    }
+
+    #[test]
+    fn test_tokens_skip_regions() {
+        let brackets = BracketSet::source_code();
+        let text = "f(\"(\\\"\") /* { */ [x] // )\n}";
+        assert_eq!(
+            brackets.tokens(text),
+            vec![(1, '('), (7, ')'), (17, '['), (19, ']'), (26, '}')]
+        );
+        // Unterminated regions extend to the end.
+        assert_eq!(brackets.tokens("( \"a)"), vec![(0, '(')]);
+        assert_eq!(
+            brackets.tokens("f::<'a>(')', '\\'')"),
+            vec![(7, '('), (17, ')')]
+        );
+        // Any character literal is skipped, lifetimes are not literals.
+        assert_eq!(
+            brackets.tokens("{ let q = '\"'; g(x) }"),
+            vec![(0, '{'), (16, '('), (18, ')'), (20, '}')]
+        );
+        assert_eq!(
+            brackets.tokens("fn f<'a>(x: &'a [u8]) -> &'a u8 { b'}' }"),
+            vec![
+                (8, '('),
+                (16, '['),
+                (19, ']'),
+                (20, ')'),
+                (32, '{'),
+                (39, '}')
+            ]
+        );
+        assert_eq!(
+            brackets.tokens("[r#\"\")}\"#, r\"(\", '\\u{7b}', '\\x28']"),
+            vec![(0, '['), (33, ']')]
+        );
+        assert_eq!(
+            brackets.tokens("('\\u{7b) }"),
+            vec![(0, '('), (4, '{'), (7, ')'), (9, '}')]
+        );
+    }
+
+    #[test]
+    fn test_scores() {
+        let brackets = BracketSet::default();
+        assert_eq!(brackets.invalid_score('>'), 25137);
+        assert_eq!(brackets.incomplete_score(']'), 2);
+        assert_eq!(brackets.invalid_score('x'), 0);
+        assert_eq!(brackets.closing('{'), Some('}'));
+        assert_eq!(brackets.opening(')'), Some('('));
+        assert_eq!(BracketSet::source_code().closing('<'), None);
+    }
+}
@@ -1,14 +1,12 @@
-use std::io::{self, BufRead};
-
-fn map_to_closing(opening: char) -> Option<char> {
-    match opening {
-        '(' => Some(')'),
-        '[' => Some(']'),
-        '{' => Some('}'),
-        '<' => Some('>'),
-        _ => None,
-    }
-}
+mod brackets;
+mod repair;
+
+use std::env;
+use std::error::Error;
+use std::io::{self, BufRead, Read};
+
+use brackets::{BracketSet, Ignore, Region};
+use repair::repair;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SyntaxCheckResult {
@@ -17,10 +15,10 @@ pub enum SyntaxCheckResult {
     Incomplete(Vec<char>),
 }
 
-fn check_syntax(line: &str) -> SyntaxCheckResult {
+fn check_syntax(line: &str, brackets: &BracketSet) -> SyntaxCheckResult {
     let mut stack = vec![];
-    for c in line.chars() {
-        if let Some(closing) = map_to_closing(c) {
+    for (_, c) in brackets.tokens(line) {
+        if let Some(closing) = brackets.closing(c) {
             stack.push(closing);
         } else if stack.last() == Some(&c) {
             stack.pop();
@@ -28,10 +26,10 @@ fn check_syntax(line: &str) -> SyntaxCheckResult {
             return SyntaxCheckResult::Invalid(c);
         }
     }
-    if stack.len() > 0 {
+    if !stack.is_empty() {
         return SyntaxCheckResult::Incomplete(stack);
     }
-    return SyntaxCheckResult::Ok;
+    SyntaxCheckResult::Ok
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -40,35 +38,15 @@ struct SyntaxCheckScore {
     incomplete_score: u64,
 }
 
-fn score_invalid_char(c: char) -> u64 {
-    match c {
-        ')' => 3,
-        ']' => 57,
-        '}' => 1197,
-        '>' => 25137,
-        _ => 0,
-    }
-}
-
-fn score_incomplete_char(c: char) -> u64 {
-    match c {
-        ')' => 1,
-        ']' => 2,
-        '}' => 3,
-        '>' => 4,
-        _ => 0,
-    }
-}
-
-fn syntax_score<R: BufRead>(reader: &mut R) -> SyntaxCheckScore {
+fn syntax_score<R: BufRead>(reader: &mut R, brackets: &BracketSet) -> SyntaxCheckScore {
     let syntax_results: Vec<SyntaxCheckResult> = reader
         .lines()
-        .map(|line| check_syntax(&line.unwrap()))
+        .map(|line| check_syntax(&line.unwrap(), brackets))
         .collect();
     let invalid_score = syntax_results
         .iter()
         .map(|result| match result {
-            SyntaxCheckResult::Invalid(c) => score_invalid_char(*c),
+            SyntaxCheckResult::Invalid(c) => brackets.invalid_score(*c),
             _ => 0,
         })
         .sum();
@@ -80,8 +58,8 @@ fn syntax_score<R: BufRead>(reader: &mut R) -> SyntaxCheckScore {
                     .iter()
                     .rev()
                     .copied()
-                    .map(score_incomplete_char)
-                    .reduce(|acc, x| acc * 5 + x)
+                    .map(|c| brackets.incomplete_score(c))
+                    .reduce(|acc, x| acc * brackets.incomplete_multiplier + x)
                     .unwrap_or_default(),
             ),
             _ => None,
@@ -91,46 +69,269 @@ fn syntax_score<R: BufRead>(reader: &mut R) -> SyntaxCheckScore {
 
     SyntaxCheckScore {
         invalid_score,
-        incomplete_score: incomplete_scores[incomplete_scores.len() / 2],
+        incomplete_score: incomplete_scores
+            .get(incomplete_scores.len() / 2)
+            .copied()
+            .unwrap_or_default(),
     }
 }
 
-fn main() {
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    repair: bool,
+    // Checks the whole input at once instead of line by line.
+    source: bool,
+    brackets: BracketSet,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, Box<dyn Error>> {
+    let (mut repair, mut source) = (false, false);
+    let mut pairs = vec![];
+    let (mut ignore, mut regions) = (vec![], vec![]);
+    let mut incomplete_multiplier = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--repair" => repair = true,
+            "--source" => source = true,
+            "--pair" => pairs.push(args.next().ok_or("missing bracket pair")?.parse()?),
+            "--ignore" => ignore.push(match args.next().ok_or("missing ignored characters")? {
+                class if class == "whitespace" => Ignore::Whitespace,
+                class if class == "alphanumeric" => Ignore::Alphanumeric,
+                class if class == "non-brackets" => Ignore::NonBrackets,
+                chars => Ignore::Chars(chars),
+            }),
+            "--region" => {
+                let start = args.next().ok_or("missing region start")?;
+                let end = args.next().ok_or("missing region end")?;
+                regions.push(Region::new(&start, &end));
+            }
+            "--multiplier" => {
+                incomplete_multiplier = Some(args.next().ok_or("missing multiplier")?.parse()?)
+            }
+            _ => return Err(format!("unknown argument {}", arg).into()),
+        }
+    }
+
+    let mut brackets = if source {
+        BracketSet::source_code()
+    } else {
+        BracketSet::default()
+    };
+    if !pairs.is_empty() {
+        brackets.pairs = pairs;
+    }
+    brackets.ignore.extend(ignore);
+    brackets.regions.extend(regions);
+    if let Some(incomplete_multiplier) = incomplete_multiplier {
+        brackets = brackets.with_incomplete_multiplier(incomplete_multiplier);
+    }
+    Ok(Options {
+        repair,
+        source,
+        brackets,
+    })
+}
+
+// The 1-based line and column of the character at `position`.
+fn line_column(text: &str, position: usize) -> (usize, usize) {
+    let before: Vec<char> = text.chars().take(position).collect();
+    let line_start = before
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |newline| newline + 1);
+    let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+    (line, position - line_start + 1)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = parse_args(env::args().skip(1))?;
+    let brackets = &options.brackets;
     let stdin = io::stdin();
-    let scores = syntax_score(&mut stdin.lock());
+    if options.repair || options.source {
+        let texts = if options.source {
+            let mut text = String::new();
+            stdin.lock().read_to_string(&mut text)?;
+            vec![text]
+        } else {
+            stdin.lock().lines().collect::<Result<Vec<String>, _>>()?
+        };
+        for (i, text) in texts.iter().enumerate() {
+            let result = check_syntax(text, brackets);
+            if options.repair {
+                if let SyntaxCheckResult::Invalid(_) = result {
+                    let repaired = repair(text, brackets)?;
+                    if options.source {
+                        // Only the edits, as the whole text is a single line.
+                        for edit in repaired.edits {
+                            let (line, column) = line_column(text, edit.position());
+                            println!("Line {}, column {}: {}", line, column, edit);
+                        }
+                        if !repaired.completion.is_empty() {
+                            println!("Append '{}'", repaired.completion);
+                        }
+                        continue;
+                    }
+                    println!("Line {}: {}{}", i + 1, repaired.line, repaired.completion);
+                    for edit in repaired.edits {
+                        println!("    {}", edit);
+                    }
+                }
+            } else {
+                match result {
+                    SyntaxCheckResult::Ok => println!("Ok"),
+                    SyntaxCheckResult::Invalid(c) => println!("Invalid character '{}'", c),
+                    SyntaxCheckResult::Incomplete(stack) => println!(
+                        "Incomplete, missing '{}'",
+                        stack.iter().rev().collect::<String>()
+                    ),
+                }
+            }
+        }
+        return Ok(());
+    }
+    let scores = syntax_score(&mut stdin.lock(), brackets);
     println!("Score of corrupt lines: {}", scores.invalid_score);
     println!("Score of incomplete lines: {}", scores.incomplete_score);
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use brackets::BracketPair;
 
     static TEST_INPUT: &str = include_str!("../test.input");
 
     #[test]
     fn test_check_syntax_ok() {
-        assert_eq!(check_syntax("<([{}])>"), SyntaxCheckResult::Ok);
+        assert_eq!(
+            check_syntax("<([{}])>", &BracketSet::default()),
+            SyntaxCheckResult::Ok
+        );
     }
 
     #[test]
     fn test_check_syntax_invalid() {
-        assert_eq!(check_syntax("{()()()>"), SyntaxCheckResult::Invalid('>'));
+        assert_eq!(
+            check_syntax("{()()()>", &BracketSet::default()),
+            SyntaxCheckResult::Invalid('>')
+        );
     }
 
     #[test]
     fn test_check_syntax_incomplete() {
         assert_eq!(
-            check_syntax("([]{()}<"),
+            check_syntax("([]{()}<", &BracketSet::default()),
             SyntaxCheckResult::Incomplete(vec![')', '>'])
         );
     }
 
+    #[test]
+    fn test_check_source_code() {
+        let brackets = BracketSet::source_code();
+        let source = "fn main() {\n    let s = \"}\"; // (\n    if a < b { f(s[0]) }\n}\n";
+        assert_eq!(check_syntax(source, &brackets), SyntaxCheckResult::Ok);
+        assert_eq!(
+            check_syntax("fn f(x: Vec<u8>) { x[0] )", &brackets),
+            SyntaxCheckResult::Invalid(')')
+        );
+        assert_eq!(
+            check_syntax("/* ) */ if (a", &brackets),
+            SyntaxCheckResult::Incomplete(vec![')'])
+        );
+    }
+
+    #[test]
+    fn test_line_column() {
+        let text = "ab\ncd\n\ne";
+        assert_eq!(line_column(text, 0), (1, 1));
+        assert_eq!(line_column(text, 2), (1, 3));
+        assert_eq!(line_column(text, 4), (2, 2));
+        assert_eq!(line_column(text, 6), (3, 1));
+        assert_eq!(line_column(text, 7), (4, 1));
+    }
+
+    #[test]
+    fn test_custom_brackets() {
+        let brackets = BracketSet::new(vec!["():1:1".parse().unwrap(), "«»:10:2".parse().unwrap()])
+            .with_ignore(Ignore::Whitespace)
+            .with_ignore(Ignore::Alphanumeric)
+            .with_incomplete_multiplier(3);
+        assert_eq!(
+            check_syntax("« a ( b ) »", &brackets),
+            SyntaxCheckResult::Ok
+        );
+        assert_eq!(
+            check_syntax("(]", &brackets),
+            SyntaxCheckResult::Invalid(']')
+        );
+        let mut buf: &[u8] = "(»\n(«\n".as_bytes();
+        assert_eq!(
+            syntax_score(&mut buf, &brackets),
+            SyntaxCheckScore {
+                invalid_score: 10,
+                incomplete_score: 2 * 3 + 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = [
+            "--pair",
+            "():3:1",
+            "--pair",
+            "[]",
+            "--ignore",
+            "whitespace",
+            "--ignore",
+            ";,",
+            "--region",
+            "#",
+            "\n",
+            "--multiplier",
+            "4",
+        ]
+        .iter()
+        .map(|arg| arg.to_string());
+        let options = parse_args(args).unwrap();
+        assert!(!options.repair && !options.source);
+        assert_eq!(
+            options.brackets.pairs,
+            vec![
+                BracketPair {
+                    opening: '(',
+                    closing: ')',
+                    invalid_score: 3,
+                    incomplete_score: 1,
+                },
+                BracketPair {
+                    opening: '[',
+                    closing: ']',
+                    invalid_score: 0,
+                    incomplete_score: 0,
+                },
+            ]
+        );
+        assert_eq!(
+            options.brackets.ignore,
+            vec![Ignore::Whitespace, Ignore::Chars(";,".into())]
+        );
+        assert_eq!(options.brackets.regions, vec![Region::new("#", "\n")]);
+        assert_eq!(options.brackets.incomplete_multiplier, 4);
+
+        let options =
+            parse_args(["--source", "--repair"].iter().map(|arg| arg.to_string())).unwrap();
+        assert!(options.repair && options.source);
+        assert_eq!(options.brackets, BracketSet::source_code());
+        assert!(parse_args(["--pair", "("].iter().map(|arg| arg.to_string())).is_err());
+    }
+
     #[test]
     fn test_syntax_score() {
         let mut buf: &[u8] = TEST_INPUT.as_bytes();
         assert_eq!(
-            syntax_score(&mut buf),
+            syntax_score(&mut buf, &BracketSet::default()),
             SyntaxCheckScore {
                 invalid_score: 26397,
                 incomplete_score: 288957,
@@ -0,0 +1,650 @@
+use std::error::Error;
+use std::fmt::Display;
+
+use crate::{brackets::BracketSet, check_syntax, SyntaxCheckResult};
+
+// Positions refer to the characters of the original line, including ignored
+// ones. Insertions happen before the character at their position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit {
+    Substitute {
+        position: usize,
+        from: char,
+        to: char,
+    },
+    Insert {
+        position: usize,
+        c: char,
+    },
+    Delete {
+        position: usize,
+        c: char,
+    },
+}
+
+impl Edit {
+    pub fn position(&self) -> usize {
+        match *self {
+            Edit::Substitute { position, .. }
+            | Edit::Insert { position, .. }
+            | Edit::Delete { position, .. } => position,
+        }
+    }
+}
+
+impl Display for Edit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Edit::Substitute { position, from, to } => {
+                write!(f, "substitute '{}' with '{}' at {}", from, to, position)
+            }
+            Edit::Insert { position, c } => write!(f, "insert '{}' at {}", c, position),
+            Edit::Delete { position, c } => write!(f, "delete '{}' at {}", c, position),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Repair {
+    pub line: String,
+    pub edits: Vec<Edit>,
+    // Closing characters to append to the repaired line.
+    pub completion: String,
+}
+
+// Brackets within this distance of an invalid one are repaired together.
+const WINDOW_RADIUS: usize = 64;
+// Longer lines are rejected instead of repaired.
+pub const MAX_TOKENS: usize = 100_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TooLongError(usize);
+
+impl Display for TooLongError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line with {} characters to check is too long to repair (at most {})",
+            self.0, MAX_TOKENS
+        )
+    }
+}
+
+impl Error for TooLongError {}
+
+// The opening bracket to substitute for a character that is none.
+fn substitute_opening(brackets: &BracketSet, c: char) -> Option<char> {
+    brackets
+        .opening(c)
+        .or_else(|| brackets.pairs.first().map(|pair| pair.opening))
+}
+
+// Substitutions turning `a` and `b` into a matching pair.
+fn pair_edits(brackets: &BracketSet, a: char, b: char) -> Option<(Option<char>, Option<char>)> {
+    let opening = match brackets.closing(a) {
+        Some(_) => a,
+        None => substitute_opening(brackets, b)?,
+    };
+    let closing = brackets.closing(opening)?;
+    Some((
+        Some(opening).filter(|&opening| opening != a),
+        Some(closing).filter(|&closing| closing != b),
+    ))
+}
+
+fn is_flip(brackets: &BracketSet, from: char, to: char) -> bool {
+    brackets.closing(from).is_some() != brackets.closing(to).is_some()
+}
+
+// Among fixes with the fewest edits, those keeping opening brackets opening
+// and closing brackets closing are preferred. Thus, costs count an edit as
+// `weight` and each flip of a bracket's direction as 1.
+fn pair_cost(brackets: &BracketSet, a: char, b: char, weight: usize) -> Option<usize> {
+    let cost = |from: char, to: Option<char>| match to {
+        Some(to) => weight + is_flip(brackets, from, to) as usize,
+        None => 0,
+    };
+    let (opening, closing) = pair_edits(brackets, a, b)?;
+    Some(cost(a, opening) + cost(b, closing))
+}
+
+// Works on a window of the line's tokens, i.e. without ignored characters.
+// The window follows the unclosed opening brackets `outer`, which it may close.
+struct Solver<'a> {
+    brackets: &'a BracketSet,
+    outer: &'a [char],
+    positions: Vec<usize>,
+    chars: Vec<char>,
+    // The position of the first character after the window.
+    end: usize,
+    // Minimum cost to balance `chars[i..j]` and whether `chars[i]`
+    // gets paired with `chars[k]` in an optimal solution.
+    balance: Vec<Vec<(usize, Option<usize>)>>,
+    // Minimum cost to make `chars[i..]` a valid prefix, i.e.
+    // balanced segments separated by unclosed opening brackets, and the end of
+    // the first balanced segment in an optimal solution.
+    prefix: Vec<(usize, Option<usize>)>,
+    // Minimum cost to make `chars[i..]` a valid prefix after `m` of the outer
+    // brackets have been closed, and the closing character of the next one.
+    closes: Vec<Vec<(usize, Option<usize>)>>,
+}
+
+impl<'a> Solver<'a> {
+    fn new(
+        tokens: &[(usize, char)],
+        outer: &'a [char],
+        end: usize,
+        brackets: &'a BracketSet,
+    ) -> Self {
+        let (positions, chars): (Vec<usize>, Vec<char>) = tokens.iter().copied().unzip();
+        let n = chars.len();
+        let weight = n + 1;
+
+        // The costs only depend on the characters, of which there are few.
+        let mut distinct = chars.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let kinds: Vec<usize> = chars
+            .iter()
+            .map(|c| distinct.binary_search(c).unwrap())
+            .collect();
+        let pair_costs: Vec<Vec<Option<usize>>> = distinct
+            .iter()
+            .map(|&a| {
+                distinct
+                    .iter()
+                    .map(|&b| pair_cost(brackets, a, b, weight))
+                    .collect()
+            })
+            .collect();
+
+        let mut balance = vec![vec![(0, None); n + 1]; n + 1];
+        for len in 1..=n {
+            for i in 0..=n - len {
+                let j = i + len;
+                let mut best = (balance[i + 1][j].0 + weight, None);
+                let costs = &pair_costs[kinds[i]];
+                for k in i + 1..j {
+                    let cost = match costs[kinds[k]] {
+                        Some(cost) => cost + balance[i + 1][k].0 + balance[k + 1][j].0,
+                        None => continue,
+                    };
+                    if cost < best.0 {
+                        best = (cost, Some(k));
+                    }
+                }
+                balance[i][j] = best;
+            }
+        }
+
+        let mut prefix = vec![(0, None); n + 1];
+        for i in (0..n).rev() {
+            let mut best = (balance[i][n].0, None);
+            for j in i..n {
+                let unclosed = match (
+                    brackets.closing(chars[j]),
+                    substitute_opening(brackets, chars[j]),
+                ) {
+                    (Some(_), _) => 0,
+                    (None, Some(opening)) => weight + is_flip(brackets, chars[j], opening) as usize,
+                    (None, None) => continue,
+                };
+                let cost = balance[i][j].0 + unclosed + prefix[j + 1].0;
+                if cost < best.0 {
+                    best = (cost, Some(j));
+                }
+            }
+            prefix[i] = best;
+        }
+
+        // At most one outer bracket is closed per character.
+        let num_outer = outer.len().min(n);
+        let mut closes = vec![vec![(0, None); num_outer + 1]; n + 1];
+        for i in (0..n).rev() {
+            for m in 0..=num_outer {
+                let mut best = (prefix[i].0, None);
+                if m < num_outer {
+                    let opening = outer[outer.len() - 1 - m];
+                    for j in i..n {
+                        let cost = match pair_cost(brackets, opening, chars[j], weight) {
+                            Some(cost) => cost + balance[i][j].0 + closes[j + 1][m + 1].0,
+                            None => continue,
+                        };
+                        if cost < best.0 {
+                            best = (cost, Some(j));
+                        }
+                    }
+                }
+                closes[i][m] = best;
+            }
+        }
+
+        Self {
+            brackets,
+            outer,
+            positions,
+            chars,
+            end,
+            balance,
+            prefix,
+            closes,
+        }
+    }
+
+    fn position(&self, i: usize) -> usize {
+        self.positions.get(i).copied().unwrap_or(self.end)
+    }
+
+    fn balance_edits(&self, i: usize, j: usize, edits: &mut Vec<Edit>) {
+        if i >= j {
+            return;
+        }
+        let c = self.chars[i];
+        match self.balance[i][j].1 {
+            // An opening bracket wraps the rest of the segment,
+            // anything else is dropped.
+            None => match self.brackets.closing(c) {
+                Some(closing) => {
+                    self.balance_edits(i + 1, j, edits);
+                    edits.push(Edit::Insert {
+                        position: self.position(j),
+                        c: closing,
+                    });
+                }
+                None => {
+                    edits.push(Edit::Delete {
+                        position: self.position(i),
+                        c,
+                    });
+                    self.balance_edits(i + 1, j, edits);
+                }
+            },
+            Some(k) => {
+                let (opening, closing) = pair_edits(self.brackets, c, self.chars[k]).unwrap();
+                if let Some(to) = opening {
+                    edits.push(Edit::Substitute {
+                        position: self.position(i),
+                        from: c,
+                        to,
+                    });
+                }
+                self.balance_edits(i + 1, k, edits);
+                if let Some(to) = closing {
+                    edits.push(Edit::Substitute {
+                        position: self.position(k),
+                        from: self.chars[k],
+                        to,
+                    });
+                }
+                self.balance_edits(k + 1, j, edits);
+            }
+        }
+    }
+
+    fn prefix_edits(&self, i: usize, edits: &mut Vec<Edit>) {
+        let n = self.chars.len();
+        match self.prefix.get(i).and_then(|&(_, j)| j) {
+            None => self.balance_edits(i, n, edits),
+            Some(j) => {
+                self.balance_edits(i, j, edits);
+                let c = self.chars[j];
+                if self.brackets.closing(c).is_none() {
+                    edits.push(Edit::Substitute {
+                        position: self.position(j),
+                        from: c,
+                        to: substitute_opening(self.brackets, c).unwrap(),
+                    });
+                }
+                self.prefix_edits(j + 1, edits);
+            }
+        }
+    }
+
+    fn close_edits(&self, i: usize, m: usize, edits: &mut Vec<Edit>) {
+        match self.closes.get(i).and_then(|closes| closes[m].1) {
+            None => self.prefix_edits(i, edits),
+            Some(j) => {
+                self.balance_edits(i, j, edits);
+                let opening = self.outer[self.outer.len() - 1 - m];
+                let (_, closing) = pair_edits(self.brackets, opening, self.chars[j]).unwrap();
+                if let Some(to) = closing {
+                    edits.push(Edit::Substitute {
+                        position: self.position(j),
+                        from: self.chars[j],
+                        to,
+                    });
+                }
+                self.close_edits(j + 1, m + 1, edits);
+            }
+        }
+    }
+
+    fn edits(&self) -> Vec<Edit> {
+        let mut edits = vec![];
+        self.close_edits(0, 0, &mut edits);
+        edits
+    }
+}
+
+// Pushes opening and pops closing brackets, or returns `false` at an invalid
+// character.
+fn scan(stack: &mut Vec<char>, chars: impl Iterator<Item = char>, brackets: &BracketSet) -> bool {
+    for c in chars {
+        if brackets.closing(c).is_some() {
+            stack.push(c);
+        } else if stack.last().and_then(|&opening| brackets.closing(opening)) == Some(c) {
+            stack.pop();
+        } else {
+            return false;
+        }
+    }
+    true
+}
+
+// The characters of a window after applying its edits.
+fn apply(tokens: &[(usize, char)], edits: &[Edit], end: usize) -> Vec<char> {
+    let mut chars = vec![];
+    let mut edits = edits.iter().peekable();
+    let positions = tokens
+        .iter()
+        .map(|&(position, c)| (position, Some(c)))
+        .chain(std::iter::once((end, None)));
+    for (position, c) in positions {
+        let mut replacement = c;
+        while let Some(edit) = edits.next_if(|edit| edit.position() == position) {
+            match *edit {
+                Edit::Insert { c, .. } => chars.push(c),
+                Edit::Substitute { to, .. } => replacement = Some(to),
+                Edit::Delete { .. } => replacement = None,
+            }
+        }
+        chars.extend(replacement);
+    }
+    chars
+}
+
+fn sort_edits(edits: &mut [Edit]) {
+    edits.sort_by_key(|edit| (edit.position(), !matches!(edit, Edit::Insert { .. })));
+}
+
+// Finds substitutions, insertions and deletions after which the line is not
+// corrupted anymore. Each invalid character is repaired together with the
+// characters around it with a minimum number of edits, which gives a minimum
+// for the whole line if it is short.
+pub fn repair(line: &str, brackets: &BracketSet) -> Result<Repair, TooLongError> {
+    let chars: Vec<char> = line.chars().collect();
+    let tokens = brackets.tokens(line);
+    if tokens.len() > MAX_TOKENS {
+        return Err(TooLongError(tokens.len()));
+    }
+
+    let mut edits = vec![];
+    // The tokens before `start` are final and leave `stack` unclosed.
+    let (mut start, mut stack) = (0, vec![]);
+    while start < tokens.len() {
+        let mut scanned = stack.clone();
+        let invalid = (start..tokens.len())
+            .find(|&i| !scan(&mut scanned, std::iter::once(tokens[i].1), brackets));
+        let invalid = match invalid {
+            Some(invalid) => invalid,
+            None => break,
+        };
+        let (window_start, window_end) = if tokens.len() <= 2 * WINDOW_RADIUS {
+            (start, tokens.len())
+        } else {
+            (
+                invalid.saturating_sub(WINDOW_RADIUS).max(start),
+                (invalid + WINDOW_RADIUS).min(tokens.len()),
+            )
+        };
+        scan(
+            &mut stack,
+            tokens[start..window_start].iter().map(|&(_, c)| c),
+            brackets,
+        );
+        let window = &tokens[window_start..window_end];
+        let end = tokens
+            .get(window_end)
+            .map_or(chars.len(), |&(position, _)| position);
+        let mut window_edits = Solver::new(window, &stack, end, brackets).edits();
+        sort_edits(&mut window_edits);
+        let repaired = apply(window, &window_edits, end);
+        let is_valid = scan(&mut stack, repaired.into_iter(), brackets);
+        debug_assert!(is_valid, "window repair left an invalid character");
+        edits.extend(window_edits);
+        start = window_end;
+    }
+    sort_edits(&mut edits);
+
+    let mut repaired = String::with_capacity(line.len() + edits.len());
+    let mut edits_iter = edits.iter().peekable();
+    for position in 0..=chars.len() {
+        let mut replacement = chars.get(position).copied();
+        while let Some(edit) = edits_iter.next_if(|edit| edit.position() == position) {
+            match *edit {
+                Edit::Insert { c, .. } => repaired.push(c),
+                Edit::Substitute { to, .. } => replacement = Some(to),
+                Edit::Delete { .. } => replacement = None,
+            }
+        }
+        repaired.extend(replacement);
+    }
+
+    let completion = match check_syntax(&repaired, brackets) {
+        SyntaxCheckResult::Incomplete(stack) => stack.iter().rev().collect(),
+        _ => String::new(),
+    };
+    Ok(Repair {
+        line: repaired,
+        edits,
+        completion,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALPHABET: [char; 5] = ['(', ')', '[', ']', '<'];
+
+    fn is_corrupted(line: &str) -> bool {
+        matches!(
+            check_syntax(line, &BracketSet::default()),
+            SyntaxCheckResult::Invalid(_)
+        )
+    }
+
+    fn single_edits(line: &str) -> Vec<String> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut edited = vec![];
+        for i in 0..=chars.len() {
+            for &c in &ALPHABET {
+                let mut inserted = chars.clone();
+                inserted.insert(i, c);
+                edited.push(inserted.into_iter().collect());
+                if i < chars.len() {
+                    let mut substituted = chars.clone();
+                    substituted[i] = c;
+                    edited.push(substituted.into_iter().collect());
+                }
+            }
+            if i < chars.len() {
+                let mut deleted = chars.clone();
+                deleted.remove(i);
+                edited.push(deleted.into_iter().collect());
+            }
+        }
+        edited
+    }
+
+    // The minimum number of edits if it is at most two.
+    fn brute_force(line: &str) -> Option<usize> {
+        if !is_corrupted(line) {
+            return Some(0);
+        }
+        let once = single_edits(line);
+        if once.iter().any(|line| !is_corrupted(line)) {
+            return Some(1);
+        }
+        once.iter()
+            .flat_map(|line| single_edits(line))
+            .any(|line| !is_corrupted(&line))
+            .then_some(2)
+    }
+
+    #[test]
+    fn test_repair_substitution() {
+        assert_eq!(
+            repair("{()()()>", &BracketSet::default()).unwrap(),
+            Repair {
+                line: "{()()()}".into(),
+                edits: vec![Edit::Substitute {
+                    position: 7,
+                    from: '>',
+                    to: '}'
+                }],
+                completion: "".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_repair_insertion() {
+        assert_eq!(
+            repair("[(<>]", &BracketSet::default()).unwrap(),
+            Repair {
+                line: "[(<>)]".into(),
+                edits: vec![Edit::Insert {
+                    position: 4,
+                    c: ')'
+                }],
+                completion: "".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_repair_deletion() {
+        assert_eq!(
+            repair("(a)", &BracketSet::default()).unwrap(),
+            Repair {
+                line: "()".into(),
+                edits: vec![Edit::Delete {
+                    position: 1,
+                    c: 'a'
+                }],
+                completion: "".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_repair_keeps_valid_lines() {
+        assert_eq!(
+            repair("<([{}])>", &BracketSet::default()).unwrap().edits,
+            vec![]
+        );
+        let repaired = repair("[({(<(())[]>[[{[]{<()<>>", &BracketSet::default()).unwrap();
+        assert_eq!(repaired.edits, vec![]);
+        assert_eq!(repaired.completion, "}}]])})]");
+    }
+
+    #[test]
+    fn test_repair_corrupted_example() {
+        let repaired = repair("{([(<{}[<>[]}>{[]{[(<()>", &BracketSet::default()).unwrap();
+        assert_eq!(
+            repaired.edits,
+            vec![Edit::Substitute {
+                position: 12,
+                from: '}',
+                to: ']'
+            }]
+        );
+        assert_eq!(repaired.line, "{([(<{}[<>[]]>{[]{[(<()>");
+        assert_eq!(repaired.completion, ")]}})])}");
+    }
+
+    #[test]
+    fn test_repair_skips_ignored() {
+        let brackets = BracketSet::source_code();
+        let repaired = repair("f(x, \")\"]; // ]", &brackets).unwrap();
+        assert_eq!(
+            repaired.edits,
+            vec![Edit::Substitute {
+                position: 8,
+                from: ']',
+                to: ')'
+            }]
+        );
+        assert_eq!(repaired.line, "f(x, \")\"); // ]");
+        let repaired = repair("{ a ]", &brackets).unwrap();
+        assert_eq!(
+            repaired.edits,
+            vec![Edit::Substitute {
+                position: 4,
+                from: ']',
+                to: '}'
+            }]
+        );
+    }
+
+    #[test]
+    fn test_repair_long_lines() {
+        let brackets = BracketSet::default();
+        // Each corruption is repaired on its own by a single substitution.
+        let mut line = "([{<>}])".repeat(5000);
+        for position in [10, 4003, 4010, 39_989] {
+            line.replace_range(position..position + 1, "]");
+        }
+        let repaired = repair(&line, &brackets).unwrap();
+        assert!(!is_corrupted(&repaired.line));
+        assert_eq!(repaired.edits.len(), 4);
+
+        // Fixing the window needs the brackets opened before it.
+        let line = format!("{}]{}", "(".repeat(200), ")".repeat(199));
+        let repaired = repair(&line, &brackets).unwrap();
+        assert_eq!(
+            repaired.edits,
+            vec![Edit::Substitute {
+                position: 200,
+                from: ']',
+                to: ')'
+            }]
+        );
+        assert_eq!(repaired.completion, "");
+
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let line: String = (0..3000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                ALPHABET[state as usize % ALPHABET.len()]
+            })
+            .collect();
+        assert!(!is_corrupted(&repair(&line, &brackets).unwrap().line));
+
+        let line = "(".repeat(MAX_TOKENS + 1);
+        assert_eq!(repair(&line, &brackets), Err(TooLongError(MAX_TOKENS + 1)));
+    }
+
+    #[test]
+    fn test_repair_is_minimal() {
+        let mut state = 0x853c49e6748fea9bu64;
+        for _ in 0..300 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let len = (state % 6) as usize;
+            let line: String = (0..len)
+                .map(|i| ALPHABET[(state >> (8 + 3 * i)) as usize % ALPHABET.len()])
+                .collect();
+            let repaired = repair(&line, &BracketSet::default()).unwrap();
+            assert!(!is_corrupted(&repaired.line), "{}", line);
+            match brute_force(&line) {
+                Some(edits) => assert_eq!(repaired.edits.len(), edits, "{}", line),
+                None => assert!(repaired.edits.len() > 2, "{}", line),
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use std::error::Error;
+use std::io::{self, Write};
+
+use super::{Map, ParseError, Pos, Transform};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+    Ply,
+}
+
+impl Format {
+    pub fn from_path(path: &str) -> Result<Self, Box<dyn Error>> {
+        match path.rsplit('.').next() {
+            Some("csv") => Ok(Format::Csv),
+            Some("json") => Ok(Format::Json),
+            Some("ply") => Ok(Format::Ply),
+            _ => Err(Box::new(ParseError)),
+        }
+    }
+}
+
+// Inserts the cluster index before the extension, e.g. `map.1.ply`.
+pub fn cluster_path(path: &str, cluster: usize) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}.{}.{}", stem, cluster, extension),
+        None => format!("{}.{}", path, cluster),
+    }
+}
+
+fn sorted_beacons<const D: usize>(map: &Map<D>) -> Vec<(Pos<D>, Vec<usize>)> {
+    let mut beacons: Vec<(Pos<D>, Vec<usize>)> = map
+        .beacons
+        .iter()
+        .map(|beacon| {
+            let seen_by = map
+                .sightings
+                .get(beacon)
+                .map(|scanners| scanners.iter().copied().collect())
+                .unwrap_or_default();
+            (*beacon, seen_by)
+        })
+        .collect();
+    beacons.sort_unstable();
+    beacons
+}
+
+fn sorted_scanners<const D: usize>(map: &Map<D>) -> Vec<(usize, Transform<D>)> {
+    let mut scanners: Vec<(usize, Transform<D>)> = map
+        .scanners
+        .iter()
+        .map(|(&index, &transform)| (index, transform))
+        .collect();
+    scanners.sort_unstable_by_key(|&(index, _)| index);
+    scanners
+}
+
+fn join<T: ToString>(values: impl Iterator<Item = T>, separator: &str) -> String {
+    values
+        .map(|value| value.to_string())
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+fn axis_name(axis: usize) -> String {
+    match axis {
+        0 => "x".to_string(),
+        1 => "y".to_string(),
+        2 => "z".to_string(),
+        3 => "w".to_string(),
+        _ => format!("x{}", axis),
+    }
+}
+
+pub fn write_csv<W: Write, const D: usize>(map: &Map<D>, out: &mut W) -> io::Result<()> {
+    writeln!(
+        out,
+        "kind,index,{},seen_by,rotation,confidence",
+        join((0..D).map(axis_name), ",")
+    )?;
+    for (i, (beacon, seen_by)) in sorted_beacons(map).iter().enumerate() {
+        writeln!(
+            out,
+            "beacon,{},{},{},,",
+            i,
+            join(beacon.iter(), ","),
+            join(seen_by.iter(), ";")
+        )?;
+    }
+    for (index, transform) in sorted_scanners(map) {
+        writeln!(
+            out,
+            "scanner,{},{},,{},{}",
+            index,
+            join(transform.translation.iter(), ","),
+            join(transform.rotation.iter().flatten(), ";"),
+            map.confidences[&index]
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write_json<W: Write, const D: usize>(map: &Map<D>, out: &mut W) -> io::Result<()> {
+    let beacons = sorted_beacons(map).into_iter().map(|(beacon, seen_by)| {
+        format!(
+            "{{\"position\": [{}], \"seen_by\": [{}]}}",
+            join(beacon.iter(), ", "),
+            join(seen_by.iter(), ", ")
+        )
+    });
+    let scanners = sorted_scanners(map).into_iter().map(|(index, transform)| {
+        let rotation = transform
+            .rotation
+            .iter()
+            .map(|row| format!("[{}]", join(row.iter(), ", ")));
+        format!(
+            "{{\"index\": {}, \"position\": [{}], \"rotation\": [{}], \"confidence\": {}}}",
+            index,
+            join(transform.translation.iter(), ", "),
+            join(rotation, ", "),
+            map.confidences[&index]
+        )
+    });
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"beacons\": [")?;
+    writeln!(out, "    {}", join(beacons, ",\n    "))?;
+    writeln!(out, "  ],")?;
+    writeln!(out, "  \"scanners\": [")?;
+    writeln!(out, "    {}", join(scanners, ",\n    "))?;
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
+
+// Beacons are white and scanners red. The `scanner` property holds the index of
+// a scanner vertex and -1 for beacons, `sightings` the number of scanners that
+// saw a beacon. The scanner rotations are given as comments. Maps with less
+// than three dimensions are embedded with the remaining coordinates set to 0.
+pub fn write_ply<W: Write, const D: usize>(map: &Map<D>, out: &mut W) -> io::Result<()> {
+    if D > 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "PLY supports at most three dimensions",
+        ));
+    }
+    let vertex = |p: &Pos<D>| {
+        let mut coordinates = [0; 3];
+        coordinates[..D].copy_from_slice(p);
+        join(coordinates.iter(), " ")
+    };
+    let beacons = sorted_beacons(map);
+    let scanners = sorted_scanners(map);
+    writeln!(out, "ply")?;
+    writeln!(out, "format ascii 1.0")?;
+    for (index, transform) in scanners.iter() {
+        writeln!(
+            out,
+            "comment scanner {} rotation {}",
+            index,
+            join(transform.rotation.iter().flatten(), " ")
+        )?;
+    }
+    writeln!(out, "element vertex {}", beacons.len() + scanners.len())?;
+    for property in ["float x", "float y", "float z"] {
+        writeln!(out, "property {}", property)?;
+    }
+    for property in ["uchar red", "uchar green", "uchar blue"] {
+        writeln!(out, "property {}", property)?;
+    }
+    writeln!(out, "property int scanner")?;
+    writeln!(out, "property uint sightings")?;
+    writeln!(out, "end_header")?;
+    for (beacon, seen_by) in beacons.iter() {
+        writeln!(out, "{} 255 255 255 -1 {}", vertex(beacon), seen_by.len())?;
+    }
+    for (index, transform) in scanners.iter() {
+        writeln!(
+            out,
+            "{} 255 0 0 {} 0",
+            vertex(&transform.translation),
+            index
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write<W: Write, const D: usize>(
+    map: &Map<D>,
+    format: Format,
+    out: &mut W,
+) -> io::Result<()> {
+    match format {
+        Format::Csv => write_csv(map, out),
+        Format::Json => write_json(map, out),
+        Format::Ply => write_ply(map, out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AlignmentConfig, Scanner};
+
+    fn example_map() -> Map {
+        let mut map = Map::new(AlignmentConfig::default());
+        map.insert(
+            0,
+            &Scanner::new(&[[0, 2, 0], [4, 1, 1]]),
+            Transform::identity(),
+        );
+        map.insert(
+            1,
+            &Scanner::new(&[[-4, 1, -1], [-1, 2, 3]]),
+            Transform {
+                rotation: [[-1, 0, 0], [0, 1, 0], [0, 0, -1]],
+                translation: [0, 0, 0],
+            },
+        );
+        map
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path("map.ply").unwrap(), Format::Ply);
+        assert_eq!(Format::from_path("out/map.json").unwrap(), Format::Json);
+        assert_eq!(Format::from_path("map.csv").unwrap(), Format::Csv);
+        assert!(Format::from_path("map.txt").is_err());
+    }
+
+    #[test]
+    fn test_cluster_path() {
+        assert_eq!(cluster_path("out/map.ply", 2), "out/map.2.ply");
+        assert_eq!(cluster_path("map", 0), "map.0");
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut out = vec![];
+        write_csv(&example_map(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+kind,index,x,y,z,seen_by,rotation,confidence
+beacon,0,0,2,0,0,,
+beacon,1,1,2,-3,1,,
+beacon,2,4,1,1,0;1,,
+scanner,0,0,0,0,,1;0;0;0;1;0;0;0;1,1
+scanner,1,0,0,0,,-1;0;0;0;1;0;0;0;-1,0.5
+"
+        );
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut out = vec![];
+        write_json(&example_map(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{
+  "beacons": [
+    {"position": [0, 2, 0], "seen_by": [0]},
+    {"position": [1, 2, -3], "seen_by": [1]},
+    {"position": [4, 1, 1], "seen_by": [0, 1]}
+  ],
+  "scanners": [
+    {"index": 0, "position": [0, 0, 0], "rotation": [[1, 0, 0], [0, 1, 0], [0, 0, 1]], "confidence": 1},
+    {"index": 1, "position": [0, 0, 0], "rotation": [[-1, 0, 0], [0, 1, 0], [0, 0, -1]], "confidence": 0.5}
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_write_other_dimensions() {
+        let mut map = Map::new(AlignmentConfig::default());
+        map.insert(0, &Scanner::new(&[[3, -1]]), Transform::identity());
+        let mut out = vec![];
+        write_csv(&map, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+kind,index,x,y,seen_by,rotation,confidence
+beacon,0,3,-1,0,,
+scanner,0,0,0,,1;0;0;1,1
+"
+        );
+        let mut out = vec![];
+        write_ply(&map, &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("3 -1 0 255 255 255 -1 1\n0 0 0 255 0 0 0 0\n"));
+
+        let mut map = Map::new(AlignmentConfig::default());
+        map.insert(0, &Scanner::new(&[[3, -1, 0, 2]]), Transform::identity());
+        assert!(write_ply(&map, &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn test_write_ply() {
+        let mut out = vec![];
+        write_ply(&example_map(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+ply
+format ascii 1.0
+comment scanner 0 rotation 1 0 0 0 1 0 0 0 1
+comment scanner 1 rotation -1 0 0 0 1 0 0 0 -1
+element vertex 5
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property int scanner
+property uint sightings
+end_header
+0 2 0 255 255 255 -1 1
+1 2 -3 255 255 255 -1 1
+4 1 1 255 255 255 -1 2
+0 0 0 255 0 0 0 0
+0 0 0 255 0 0 1 0
+"
+        );
+    }
+}
@@ -1,235 +1,667 @@
-use std::collections::{HashSet, VecDeque};
+mod export;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::convert::TryInto;
+use std::env;
 use std::error::Error;
 use std::fmt::Display;
-use std::io::{self, BufRead};
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter};
 use std::iter::FromIterator;
+use std::ops::RangeInclusive;
 
-type Pos = (i64, i64, i64);
+type Pos<const D: usize = 3> = [i64; D];
 
-fn dist(a: &Pos, b: &Pos) -> u64 {
-    (a.0 - b.0).pow(2) as u64 + (a.1 - b.1).pow(2) as u64 + (a.2 - b.2).pow(2) as u64
+fn dist<const D: usize>(a: &Pos<D>, b: &Pos<D>) -> u64 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y).pow(2) as u64)
+        .sum()
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-struct Scanner {
-    beacons: HashSet<Pos>,
-    dists: HashSet<u64>,
-    scanner: Pos,
+fn add<const D: usize>(a: &Pos<D>, b: &Pos<D>) -> Pos<D> {
+    let mut result = *a;
+    for (value, x) in result.iter_mut().zip(b.iter()) {
+        *value += x;
+    }
+    result
 }
 
-impl Scanner {
-    fn new(beacons: &[Pos]) -> Self {
-        Self {
-            beacons: HashSet::from_iter(beacons.iter().cloned()),
-            dists: beacons
-                .iter()
-                .enumerate()
-                .flat_map(|(i, a)| beacons[i + 1..].iter().map(move |b| dist(a, b)))
-                .collect(),
-            scanner: (0, 0, 0),
+fn sub<const D: usize>(a: &Pos<D>, b: &Pos<D>) -> Pos<D> {
+    let mut result = *a;
+    for (value, x) in result.iter_mut().zip(b.iter()) {
+        *value -= x;
+    }
+    result
+}
+
+fn chebyshev_dist<const D: usize>(a: &Pos<D>, b: &Pos<D>) -> i64 {
+    sub(a, b).iter().map(|x| x.abs()).max().unwrap_or(0)
+}
+
+fn manhatten_dist<const D: usize>(a: &Pos<D>, b: &Pos<D>) -> u64 {
+    a.iter().zip(b.iter()).map(|(x, y)| x.abs_diff(*y)).sum()
+}
+
+type Rotation<const D: usize = 3> = [[i64; D]; D];
+
+fn rotate<const D: usize>(rotation: &Rotation<D>, p: &Pos<D>) -> Pos<D> {
+    let mut result = [0; D];
+    for (value, row) in result.iter_mut().zip(rotation.iter()) {
+        *value = row.iter().zip(p.iter()).map(|(a, b)| a * b).sum();
+    }
+    result
+}
+
+fn mul<const D: usize>(a: &Rotation<D>, b: &Rotation<D>) -> Rotation<D> {
+    let mut result = [[0; D]; D];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..D).map(|k| a[i][k] * b[k][j]).sum();
         }
     }
+    result
+}
+
+fn transpose<const D: usize>(rotation: &Rotation<D>) -> Rotation<D> {
+    let mut result = [[0; D]; D];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = rotation[j][i];
+        }
+    }
+    result
+}
+
+fn identity_rotation<const D: usize>() -> Rotation<D> {
+    let mut result = [[0; D]; D];
+    for (i, row) in result.iter_mut().enumerate() {
+        row[i] = 1;
+    }
+    result
+}
+
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![vec![]];
+    }
+    permutations(n - 1)
+        .into_iter()
+        .flat_map(|permutation| {
+            (0..n).map(move |i| {
+                let mut permutation = permutation.clone();
+                permutation.insert(i, n - 1);
+                permutation
+            })
+        })
+        .collect()
+}
+
+fn permutation_sign(permutation: &[usize]) -> i64 {
+    let inversions = permutation
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| permutation[i + 1..].iter().filter(move |b| a > b))
+        .count();
+    if inversions % 2 == 0 {
+        1
+    } else {
+        -1
+    }
+}
 
-    fn rotate_around_x(&self) -> Self {
+// The proper rotations mapping axes onto axes are exactly the signed
+// permutation matrices with a determinant of 1. The determinant of such a
+// matrix is the sign of the permutation times the product of the signs.
+fn proper_rotations<const D: usize>() -> Vec<Rotation<D>> {
+    permutations(D)
+        .iter()
+        .flat_map(|permutation| {
+            (0..1usize << D).filter_map(move |signs| {
+                let mut rotation = [[0; D]; D];
+                let mut det = permutation_sign(permutation);
+                for (i, &j) in permutation.iter().enumerate() {
+                    rotation[i][j] = if signs & (1 << i) == 0 { 1 } else { -1 };
+                    det *= rotation[i][j];
+                }
+                if det == 1 {
+                    Some(rotation)
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Transform<const D: usize = 3> {
+    rotation: Rotation<D>,
+    translation: Pos<D>,
+}
+
+impl<const D: usize> Transform<D> {
+    fn identity() -> Self {
         Self {
-            beacons: self
-                .beacons
-                .iter()
-                .map(|p| {
-                    (
-                        p.0,
-                        p.2 - self.scanner.2 + self.scanner.1,
-                        -p.1 + self.scanner.1 + self.scanner.2,
-                    )
-                })
-                .collect(),
-            dists: self.dists.clone(),
-            scanner: self.scanner,
+            rotation: identity_rotation(),
+            translation: [0; D],
         }
     }
 
-    fn rotate_around_y(&self) -> Self {
+    fn apply(&self, p: &Pos<D>) -> Pos<D> {
+        add(&rotate(&self.rotation, p), &self.translation)
+    }
+
+    // Applies `other` first and then `self`.
+    fn compose(&self, other: &Transform<D>) -> Self {
         Self {
-            beacons: self
-                .beacons
-                .iter()
-                .map(|p| {
-                    (
-                        p.2 - self.scanner.2 + self.scanner.0,
-                        p.1,
-                        -p.0 + self.scanner.0 + self.scanner.2,
-                    )
-                })
-                .collect(),
-            dists: self.dists.clone(),
-            scanner: self.scanner,
+            rotation: mul(&self.rotation, &other.rotation),
+            translation: self.apply(&other.translation),
         }
     }
 
-    fn rotate_around_z(&self) -> Self {
+    fn inverse(&self) -> Self {
+        let rotation = transpose(&self.rotation);
+        let translation = rotate(&rotation, &self.translation);
         Self {
-            beacons: self
-                .beacons
-                .iter()
-                .map(|p| {
-                    (
-                        p.1 - self.scanner.1 + self.scanner.0,
-                        -p.0 + self.scanner.0 + self.scanner.1,
-                        p.2,
-                    )
-                })
-                .collect(),
-            dists: self.dists.clone(),
-            scanner: self.scanner,
+            rotation,
+            translation: sub(&[0; D], &translation),
         }
     }
+}
+
+const FINGERPRINT_LEN: usize = 3;
+
+// The squared distances to the nearest neighbors of a beacon within the same
+// scanner. These do not change under rotation and translation.
+type Fingerprint = [u64; FINGERPRINT_LEN];
+
+fn fingerprint<const D: usize>(beacon: &Pos<D>, beacons: &[Pos<D>]) -> Fingerprint {
+    let mut dists: Vec<u64> = beacons
+        .iter()
+        .filter(|&other| other != beacon)
+        .map(|other| dist(beacon, other))
+        .collect();
+    dists.sort_unstable();
+    let mut fingerprint = [u64::MAX; FINGERPRINT_LEN];
+    for (value, d) in fingerprint.iter_mut().zip(dists) {
+        *value = d;
+    }
+    fingerprint
+}
 
-    fn translate(&self, delta: Pos) -> Self {
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Scanner<const D: usize = 3> {
+    beacons: Vec<Pos<D>>,
+    dists: HashSet<u64>,
+    fingerprints: Vec<Fingerprint>,
+}
+
+impl<const D: usize> Scanner<D> {
+    fn new(beacons: &[Pos<D>]) -> Self {
         Self {
-            beacons: self
-                .beacons
+            beacons: beacons.to_vec(),
+            dists: beacons
                 .iter()
-                .map(|p| (p.0 + delta.0, p.1 + delta.1, p.2 + delta.2))
+                .enumerate()
+                .flat_map(|(i, a)| beacons[i + 1..].iter().map(move |b| dist(a, b)))
+                .collect(),
+            fingerprints: beacons
+                .iter()
+                .map(|beacon| fingerprint(beacon, beacons))
                 .collect(),
-            dists: self.dists.clone(),
-            scanner: (
-                self.scanner.0 + delta.0,
-                self.scanner.1 + delta.1,
-                self.scanner.2 + delta.2,
-            ),
         }
     }
 }
 
-struct ScannerOrientationsIterator {
-    cur: Scanner,
-    index: u8,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Matching {
+    // Beacons have to match exactly.
+    Strict,
+    // Beacons match if each coordinate differs by at most the given value.
+    Tolerant(i64),
 }
 
-impl ScannerOrientationsIterator {
-    fn new(scanner: Scanner) -> Self {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct AlignmentConfig {
+    min_overlap: usize,
+    matching: Matching,
+    max_hypotheses: usize,
+}
+
+impl Default for AlignmentConfig {
+    fn default() -> Self {
         Self {
-            cur: scanner,
-            index: 0,
+            min_overlap: 12,
+            matching: Matching::Strict,
+            max_hypotheses: 10000,
         }
     }
 }
 
-impl Iterator for ScannerOrientationsIterator {
-    type Item = Scanner;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index >= 24 {
-            return None;
-        }
-        self.cur = self.cur.rotate_around_x();
-        if self.index % 4 == 0 && self.index <= 16 {
-            self.cur = self.cur.rotate_around_y();
-        }
-        if self.index == 16 {
-            self.cur = self.cur.rotate_around_z();
+impl AlignmentConfig {
+    fn tolerance(&self) -> i64 {
+        match self.matching {
+            Matching::Strict => 0,
+            Matching::Tolerant(tolerance) => tolerance,
         }
-        if self.index == 20 {
-            self.cur = self.cur.rotate_around_z().rotate_around_z();
+    }
+
+    fn neighbor_dist_range(&self, d: u64, dimensions: usize) -> RangeInclusive<u64> {
+        match self.matching {
+            Matching::Strict => d..=d,
+            Matching::Tolerant(tolerance) => {
+                let max_error = 2.0 * tolerance as f64 * (dimensions as f64).sqrt();
+                let r = (d as f64).sqrt();
+                let lower = (r - max_error).max(0.0);
+                (lower * lower).floor() as u64..=((r + max_error).powi(2)).ceil() as u64
+            }
         }
-        self.index += 1;
-        return Some(self.cur.clone());
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-struct Map {
-    beacons: HashSet<Pos>,
+// All offsets with coordinates within `-r..=r`.
+fn cell_offsets<const D: usize>(r: i64) -> impl Iterator<Item = Pos<D>> {
+    let width = 2 * r + 1;
+    (0..width.pow(D as u32)).map(move |mut n| {
+        let mut offset = [0; D];
+        for value in offset.iter_mut() {
+            *value = n % width - r;
+            n /= width;
+        }
+        offset
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Map<const D: usize = 3> {
+    config: AlignmentConfig,
+    beacons: HashSet<Pos<D>>,
+    grid: HashMap<Pos<D>, Vec<Pos<D>>>,
     dists: HashSet<u64>,
-    scanners: HashSet<Pos>,
+    neighbor_dists: BTreeMap<u64, Vec<Pos<D>>>,
+    sightings: HashMap<Pos<D>, BTreeSet<usize>>,
+    scanners: HashMap<usize, Transform<D>>,
+    confidences: HashMap<usize, f64>,
 }
 
-impl Map {
-    fn new() -> Self {
+impl<const D: usize> Map<D> {
+    fn new(config: AlignmentConfig) -> Self {
         Self {
+            config,
             beacons: HashSet::new(),
+            grid: HashMap::new(),
             dists: HashSet::new(),
-            scanners: HashSet::new(),
+            neighbor_dists: BTreeMap::new(),
+            sightings: HashMap::new(),
+            scanners: HashMap::new(),
+            confidences: HashMap::new(),
+        }
+    }
+
+    fn grid_cell(&self, p: &Pos<D>) -> Pos<D> {
+        let size = self.config.tolerance().max(1);
+        p.map(|c| c.div_euclid(size))
+    }
+
+    fn find_beacon(&self, p: &Pos<D>) -> Option<Pos<D>> {
+        self.find_beacon_within(p, self.config.tolerance())
+    }
+
+    fn find_beacon_within(&self, p: &Pos<D>, tolerance: i64) -> Option<Pos<D>> {
+        match self.config.matching {
+            Matching::Strict => self.beacons.get(p).copied(),
+            Matching::Tolerant(_) => {
+                let cell = self.grid_cell(p);
+                let r = (tolerance + self.config.tolerance().max(1) - 1)
+                    / self.config.tolerance().max(1);
+                cell_offsets(r)
+                    .filter_map(|offset| self.grid.get(&add(&cell, &offset)))
+                    .flatten()
+                    .filter(|beacon| chebyshev_dist(beacon, p) <= tolerance)
+                    .min_by_key(|beacon| dist(beacon, p))
+                    .copied()
+            }
         }
     }
 
-    fn insert(&mut self, scanner: &Scanner) {
-        self.beacons.extend(scanner.beacons.iter());
+    fn insert(&mut self, index: usize, scanner: &Scanner<D>, transform: Transform<D>) {
+        let overlap = if self.scanners.is_empty() {
+            scanner.beacons.len()
+        } else {
+            self.count_overlap(scanner, &transform)
+        };
+        let beacons: Vec<Pos<D>> = scanner
+            .beacons
+            .iter()
+            .map(|b| transform.apply(b))
+            .map(|b| self.find_beacon(&b).unwrap_or(b))
+            .collect();
+        for beacon in beacons.iter() {
+            if self.beacons.insert(*beacon) {
+                let cell = self.grid_cell(beacon);
+                self.grid.entry(cell).or_default().push(*beacon);
+            }
+            self.sightings.entry(*beacon).or_default().insert(index);
+        }
         self.dists.extend(
             self.beacons
                 .iter()
-                .flat_map(|a| scanner.beacons.iter().map(move |b| dist(a, b)))
+                .flat_map(|a| beacons.iter().map(move |b| dist(a, b)))
                 .filter(|&d| d != 0),
         );
-        self.scanners.insert(scanner.scanner);
+        for (beacon, fingerprint) in beacons.iter().zip(scanner.fingerprints.iter()) {
+            for &d in fingerprint.iter().filter(|&&d| d != u64::MAX) {
+                self.neighbor_dists.entry(d).or_default().push(*beacon);
+            }
+        }
+        self.scanners.insert(index, transform);
+        self.confidences
+            .insert(index, overlap as f64 / scanner.beacons.len().max(1) as f64);
+    }
+
+    fn might_be_aligned(&self, scanner: &Scanner<D>) -> bool {
+        match self.config.matching {
+            Matching::Strict => {
+                scanner
+                    .dists
+                    .iter()
+                    .filter(|d| self.dists.contains(d))
+                    .count()
+                    >= self.config.min_overlap
+            }
+            // Noisy distances will hardly ever match exactly.
+            Matching::Tolerant(_) => true,
+        }
+    }
+
+    fn count_overlap(&self, scanner: &Scanner<D>, transform: &Transform<D>) -> usize {
+        self.count_overlap_within(scanner, transform, self.config.tolerance())
     }
 
-    fn might_be_aligned(&self, scanner: &Scanner) -> bool {
+    fn count_overlap_within(
+        &self,
+        scanner: &Scanner<D>,
+        transform: &Transform<D>,
+        tolerance: i64,
+    ) -> usize {
         scanner
-            .dists
+            .beacons
             .iter()
-            .filter(|d| self.dists.contains(d))
+            .filter(|beacon| {
+                self.find_beacon_within(&transform.apply(beacon), tolerance)
+                    .is_some()
+            })
             .count()
-            >= 12
     }
 
-    fn is_aligned(&self, scanner: &Scanner) -> bool {
+    fn is_aligned(&self, scanner: &Scanner<D>, transform: &Transform<D>) -> bool {
+        self.count_overlap(scanner, transform) >= self.config.min_overlap
+    }
+
+    // Pairs of beacons sharing a nearest neighbor distance might be the same
+    // beacon.
+    fn candidate_pairs(&self, scanner: &Scanner<D>) -> Vec<(Pos<D>, Pos<D>)> {
         scanner
             .beacons
             .iter()
-            .filter(|beacon| self.beacons.contains(beacon))
-            .count()
-            >= 12
-    }
-
-    fn try_aligned_insert(&mut self, scanner: &Scanner) -> bool {
-        if self.might_be_aligned(&scanner) {
-            for scanner in ScannerOrientationsIterator::new(scanner.clone()) {
-                let reference_beacon = scanner.beacons.iter().next().unwrap();
-                for beacon in self.beacons.iter() {
-                    let delta = (
-                        beacon.0 - reference_beacon.0,
-                        beacon.1 - reference_beacon.1,
-                        beacon.2 - reference_beacon.2,
-                    );
-                    let translated_scanner = scanner.translate(delta);
-                    if self.is_aligned(&translated_scanner) {
-                        self.insert(&translated_scanner);
-                        return true;
-                    }
-                }
+            .zip(scanner.fingerprints.iter())
+            .flat_map(|(beacon, fingerprint)| {
+                let candidates: HashSet<Pos<D>> = fingerprint
+                    .iter()
+                    .filter(|&&d| d != u64::MAX)
+                    .flat_map(|&d| {
+                        self.neighbor_dists
+                            .range(self.config.neighbor_dist_range(d, D))
+                    })
+                    .flat_map(|(_, candidates)| candidates.iter().copied())
+                    .collect();
+                candidates
+                    .into_iter()
+                    .map(move |candidate| (*beacon, candidate))
+            })
+            .collect()
+    }
+
+    // Lets each candidate pair vote for the transforms mapping one beacon onto
+    // the other. The overlapping beacons all vote for the correct transform.
+    fn vote_transform(
+        &self,
+        scanner: &Scanner<D>,
+        candidate_pairs: &[(Pos<D>, Pos<D>)],
+    ) -> Option<Transform<D>> {
+        let rotations = proper_rotations();
+        let mut votes: HashMap<Transform<D>, usize> = HashMap::new();
+        for (beacon, candidate) in candidate_pairs {
+            for rotation in rotations.iter() {
+                let translation = sub(candidate, &rotate(rotation, beacon));
+                *votes
+                    .entry(Transform {
+                        rotation: *rotation,
+                        translation,
+                    })
+                    .or_default() += 1;
+            }
+        }
+
+        let mut candidates: Vec<(Transform<D>, usize)> =
+            votes.into_iter().filter(|&(_, count)| count > 1).collect();
+        candidates.sort_unstable_by_key(|&(_, count)| Reverse(count));
+        candidates
+            .into_iter()
+            .map(|(transform, _)| transform)
+            .find(|transform| self.is_aligned(scanner, transform))
+    }
+
+    // Tries every rotation and every pairing of a scanner beacon with a map
+    // beacon. With at least `min_overlap` overlapping beacons, one of any
+    // `len - min_overlap + 1` beacons must be part of the overlap.
+    fn search_transform(&self, scanner: &Scanner<D>) -> Option<Transform<D>> {
+        let num_references = (scanner.beacons.len() + 1).saturating_sub(self.config.min_overlap);
+        proper_rotations().into_iter().find_map(|rotation| {
+            scanner
+                .beacons
+                .iter()
+                .take(num_references)
+                .find_map(|reference| {
+                    let rotated = rotate(&rotation, reference);
+                    self.beacons
+                        .iter()
+                        .map(|beacon| Transform {
+                            rotation,
+                            translation: sub(beacon, &rotated),
+                        })
+                        .find(|transform| self.is_aligned(scanner, transform))
+                })
+        })
+    }
+
+    // RANSAC-style estimation: two candidate pairs determine a rotation and
+    // translation. As the translation is only known up to the noise of both
+    // beacons, it is refined with the beacons agreeing with it at twice the
+    // tolerance before checking the overlap.
+    fn estimate_transform(
+        &self,
+        scanner: &Scanner<D>,
+        candidate_pairs: &[(Pos<D>, Pos<D>)],
+    ) -> Option<Transform<D>> {
+        let rotations = proper_rotations();
+        let tolerance = self.config.tolerance();
+        let hypotheses = candidate_pairs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| {
+                candidate_pairs[i + 1..]
+                    .iter()
+                    .filter(move |b| a.0 != b.0 && a.1 != b.1)
+                    .map(move |b| (a, b))
+            })
+            .filter_map(|((p0, q0), (p1, q1))| {
+                let scanner_delta = sub(p1, p0);
+                let map_delta = sub(q1, q0);
+                rotations
+                    .iter()
+                    .find(|rotation| {
+                        chebyshev_dist(&rotate(rotation, &scanner_delta), &map_delta)
+                            <= 2 * tolerance
+                    })
+                    .map(|rotation| Transform {
+                        rotation: *rotation,
+                        translation: sub(q0, &rotate(rotation, p0)),
+                    })
+            });
+        let mut tried = HashSet::new();
+        for transform in hypotheses.take(self.config.max_hypotheses) {
+            if !tried.insert(transform)
+                || self.count_overlap_within(scanner, &transform, 2 * tolerance)
+                    < self.config.min_overlap
+            {
+                continue;
+            }
+            let refined = self.refine(scanner, transform, 2 * tolerance);
+            if self.is_aligned(scanner, &refined) {
+                return Some(refined);
+            }
+        }
+        None
+    }
+
+    // Moves the translation to the mean offset of all matched beacons.
+    fn refine(
+        &self,
+        scanner: &Scanner<D>,
+        transform: Transform<D>,
+        tolerance: i64,
+    ) -> Transform<D> {
+        let offsets: Vec<Pos<D>> = scanner
+            .beacons
+            .iter()
+            .filter_map(|beacon| {
+                let rotated = rotate(&transform.rotation, beacon);
+                self.find_beacon_within(&transform.apply(beacon), tolerance)
+                    .map(|matched| sub(&matched, &rotated))
+            })
+            .collect();
+        let n = offsets.len() as f64;
+        let mut translation = [0; D];
+        for (i, value) in translation.iter_mut().enumerate() {
+            *value = (offsets.iter().map(|p| p[i]).sum::<i64>() as f64 / n).round() as i64;
+        }
+        Transform {
+            rotation: transform.rotation,
+            translation,
+        }
+    }
+
+    fn try_aligned_insert(&mut self, index: usize, scanner: &Scanner<D>) -> bool {
+        if self.might_be_aligned(scanner) {
+            let candidate_pairs = self.candidate_pairs(scanner);
+            let transform = match self.config.matching {
+                // Overlaps without shared fingerprints are still found, only
+                // more slowly.
+                Matching::Strict => self
+                    .vote_transform(scanner, &candidate_pairs)
+                    .or_else(|| self.search_transform(scanner)),
+                Matching::Tolerant(_) => self.estimate_transform(scanner, &candidate_pairs),
+            };
+            if let Some(transform) = transform {
+                self.insert(index, scanner, transform);
+                return true;
             }
         }
         false
     }
 
-    fn max_scanner_manhatten_dist(&self) -> u64 {
+    fn scanner_positions(&self) -> HashSet<Pos<D>> {
         self.scanners
+            .values()
+            .map(|transform| transform.translation)
+            .collect()
+    }
+
+    // Maps coordinates relative to scanner `from` to coordinates relative to
+    // scanner `to`.
+    fn relative_transform(&self, from: usize, to: usize) -> Option<Transform<D>> {
+        Some(
+            self.scanners
+                .get(&to)?
+                .inverse()
+                .compose(self.scanners.get(&from)?),
+        )
+    }
+
+    fn max_scanner_manhatten_dist(&self) -> u64 {
+        let positions = self.scanner_positions();
+        positions
             .iter()
-            .flat_map(|s0| {
-                self.scanners.iter().map(move |s1| {
-                    (s0.0 - s1.0).abs() as u64
-                        + (s0.1 - s1.1).abs() as u64
-                        + (s0.2 - s1.2).abs() as u64
-                })
-            })
+            .flat_map(|s0| positions.iter().map(move |s1| manhatten_dist(s0, s1)))
             .max()
             .unwrap_or(0)
     }
 }
 
-fn build_map(scanners: &Vec<Scanner>) -> Map {
-    let mut map = Map::new();
-    let mut queue = VecDeque::from_iter(scanners.iter());
-    map.insert(queue.pop_front().unwrap());
-    while let Some(scanner) = queue.pop_front() {
-        if !map.try_aligned_insert(scanner) {
-            queue.push_back(scanner);
+// Aligns as many scanners from the queue as possible with the first one and
+// leaves the remaining scanners in the queue.
+fn assemble_map<const D: usize>(
+    queue: &mut VecDeque<(usize, &Scanner<D>)>,
+    config: AlignmentConfig,
+) -> Map<D> {
+    let mut map = Map::new(config);
+    if let Some((index, scanner)) = queue.pop_front() {
+        map.insert(index, scanner, Transform::identity());
+    }
+    let mut failures_in_a_row = 0;
+    while failures_in_a_row < queue.len() {
+        let (index, scanner) = queue.pop_front().unwrap();
+        if map.try_aligned_insert(index, scanner) {
+            failures_in_a_row = 0;
+        } else {
+            queue.push_back((index, scanner));
+            failures_in_a_row += 1;
         }
     }
     map
 }
 
+fn build_map<const D: usize>(
+    scanners: &[Scanner<D>],
+    config: AlignmentConfig,
+) -> (Map<D>, Vec<usize>) {
+    let mut queue = VecDeque::from_iter(scanners.iter().enumerate());
+    let map = assemble_map(&mut queue, config);
+    let mut unaligned: Vec<usize> = queue.into_iter().map(|(index, _)| index).collect();
+    unaligned.sort_unstable();
+    (map, unaligned)
+}
+
+fn build_clusters<const D: usize>(scanners: &[Scanner<D>], config: AlignmentConfig) -> Vec<Map<D>> {
+    let mut queue = VecDeque::from_iter(scanners.iter().enumerate());
+    let mut clusters = vec![];
+    while !queue.is_empty() {
+        clusters.push(assemble_map(&mut queue, config));
+        queue
+            .make_contiguous()
+            .sort_unstable_by_key(|&(index, _)| index);
+    }
+    clusters
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct UnalignedScannersError(Vec<usize>);
+
+impl Display for UnalignedScannersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("could not align scanners")?;
+        for index in self.0.iter() {
+            f.write_fmt(format_args!(" {}", index))?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for UnalignedScannersError {}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct ParseError;
 
@@ -241,22 +673,18 @@ impl Display for ParseError {
 
 impl Error for ParseError {}
 
-fn parse_pos(input: &str) -> Result<Pos, Box<dyn Error>> {
-    let mut coordinates = input
+fn parse_pos<const D: usize>(input: &str) -> Result<Pos<D>, Box<dyn Error>> {
+    let coordinates = input
         .split(',')
-        .map(|part| i64::from_str_radix(part.trim(), 10));
-    Ok((
-        coordinates.next().ok_or(ParseError)??,
-        coordinates.next().ok_or(ParseError)??,
-        coordinates.next().ok_or(ParseError)??,
-    ))
-}
-
-fn parse_scanner<R: BufRead>(input: &mut R) -> Result<Scanner, Box<dyn Error>> {
-    let is_boundary = |line: &Result<String, io::Error>| {
-        line.as_ref().map_or(false, |line| line.trim().is_empty())
-    };
-    let beacons: Vec<Pos> = input
+        .map(|part| part.trim().parse::<i64>())
+        .collect::<Result<Vec<i64>, _>>()?;
+    Ok(coordinates.try_into().map_err(|_| ParseError)?)
+}
+
+fn parse_scanner<R: BufRead, const D: usize>(input: &mut R) -> Result<Scanner<D>, Box<dyn Error>> {
+    let is_boundary =
+        |line: &Result<String, io::Error>| line.as_ref().is_ok_and(|line| line.trim().is_empty());
+    let beacons: Vec<Pos<D>> = input
         .lines()
         .take_while(|line| !is_boundary(line))
         .filter_map(|line| match line {
@@ -273,7 +701,9 @@ fn parse_scanner<R: BufRead>(input: &mut R) -> Result<Scanner, Box<dyn Error>> {
     Ok(Scanner::new(&beacons))
 }
 
-fn parse_input<R: BufRead>(input: &mut R) -> Result<Vec<Scanner>, Box<dyn Error>> {
+fn parse_input<R: BufRead, const D: usize>(
+    input: &mut R,
+) -> Result<Vec<Scanner<D>>, Box<dyn Error>> {
     let mut scanners = vec![];
     let mut line = String::new();
     while input.read_line(&mut line).is_ok() {
@@ -286,18 +716,104 @@ fn parse_input<R: BufRead>(input: &mut R) -> Result<Vec<Scanner>, Box<dyn Error>
     Ok(scanners)
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let stdin = io::stdin();
-    let scanners = parse_input(&mut stdin.lock())?;
-    let map = build_map(&scanners);
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Options {
+    config: AlignmentConfig,
+    clusters: bool,
+    exports: Vec<String>,
+    dimensions: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            config: AlignmentConfig::default(),
+            clusters: false,
+            exports: vec![],
+            dimensions: 3,
+        }
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        if arg == "--clusters" {
+            options.clusters = true;
+            continue;
+        }
+        let value = args.next().ok_or(ParseError)?;
+        match arg.as_str() {
+            "--min-overlap" => options.config.min_overlap = value.parse()?,
+            "--tolerance" => options.config.matching = Matching::Tolerant(value.parse()?),
+            "--max-hypotheses" => options.config.max_hypotheses = value.parse()?,
+            "--export" => options.exports.push(value),
+            "--dimensions" => options.dimensions = value.parse()?,
+            _ => return Err(Box::new(ParseError)),
+        }
+    }
+    Ok(options)
+}
+
+fn print_map<const D: usize>(map: &Map<D>, num_scanners: usize) {
+    let reference = map.scanners.keys().min().copied().unwrap_or(0);
+    for index in 0..num_scanners {
+        if let Some(transform) = map.relative_transform(index, reference) {
+            println!(
+                "Scanner {} at {:?} with rotation {:?} (confidence {:.2})",
+                index, transform.translation, transform.rotation, map.confidences[&index]
+            );
+        }
+    }
     println!("Number of beacons: {}", map.beacons.len());
     println!(
         "Max. Manhatten distance between scanners {}",
         map.max_scanner_manhatten_dist()
     );
+}
+
+fn export_map<const D: usize>(map: &Map<D>, path: &str) -> Result<(), Box<dyn Error>> {
+    let format = export::Format::from_path(path)?;
+    let mut out = BufWriter::new(File::create(path)?);
+    export::write(map, format, &mut out)?;
+    Ok(())
+}
+
+fn run<const D: usize>(options: &Options) -> Result<(), Box<dyn Error>> {
+    let stdin = io::stdin();
+    let scanners: Vec<Scanner<D>> = parse_input(&mut stdin.lock())?;
+    if options.clusters {
+        for (i, map) in build_clusters(&scanners, options.config).iter().enumerate() {
+            println!("Cluster {}:", i);
+            print_map(map, scanners.len());
+            for path in options.exports.iter() {
+                export_map(map, &export::cluster_path(path, i))?;
+            }
+        }
+        return Ok(());
+    }
+
+    let (map, unaligned) = build_map(&scanners, options.config);
+    print_map(&map, scanners.len());
+    for path in options.exports.iter() {
+        export_map(&map, path)?;
+    }
+    if !unaligned.is_empty() {
+        return Err(Box::new(UnalignedScannersError(unaligned)));
+    }
     Ok(())
 }
 
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = parse_args(env::args().skip(1))?;
+    match options.dimensions {
+        2 => run::<2>(&options),
+        3 => run::<3>(&options),
+        4 => run::<4>(&options),
+        _ => Err(Box::new(ParseError)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -318,180 +834,469 @@ mod tests {
         assert_eq!(
             parse_input(&mut input).unwrap(),
             vec![
-                Scanner::new(&[(0, 2, 0), (4, 1, 1), (3, 3, 2)]),
-                Scanner::new(&[(-1, -1, 3), (-5, 0, 4), (-2, 1, 5)]),
+                Scanner::new(&[[0, 2, 0], [4, 1, 1], [3, 3, 2]]),
+                Scanner::new(&[[-1, -1, 3], [-5, 0, 4], [-2, 1, 5]]),
             ]
         );
     }
 
     #[test]
-    fn test_orientations_iterator() {
+    fn test_proper_rotations() {
+        let rotations: Vec<Rotation> = proper_rotations();
+        assert_eq!(rotations.len(), 24);
+        assert_eq!(
+            HashSet::<Rotation>::from_iter(rotations.iter().cloned()).len(),
+            24
+        );
+        assert!(rotations.contains(&identity_rotation()));
+        for a in rotations.iter() {
+            assert!(rotations.contains(&transpose(a)));
+            for b in rotations.iter() {
+                assert!(rotations.contains(&mul(a, b)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_orientations() {
         let scanner = Scanner::new(&[
-            (-1, -1, 1),
-            (-2, -2, 2),
-            (-3, -3, 3),
-            (-2, -3, 1),
-            (5, 6, -4),
-            (8, 0, 7),
+            [-1, -1, 1],
+            [-2, -2, 2],
+            [-3, -3, 3],
+            [-2, -3, 1],
+            [5, 6, -4],
+            [8, 0, 7],
         ]);
-        let all_orientations: Vec<Scanner> = ScannerOrientationsIterator::new(scanner).collect();
-        assert!(all_orientations.contains(&Scanner::new(&[
-            (1, -1, 1),
-            (2, -2, 2),
-            (3, -3, 3),
-            (2, -1, 3),
-            (-5, 4, -6),
-            (-8, -7, 0)
-        ])));
-        assert!(all_orientations.contains(&Scanner::new(&[
-            (-1, -1, -1),
-            (-2, -2, -2),
-            (-3, -3, -3),
-            (-1, -3, -2),
-            (4, 6, 5),
-            (-7, 0, 8)
-        ])));
-        assert!(all_orientations.contains(&Scanner::new(&[
-            (1, 1, -1),
-            (2, 2, -2),
-            (3, 3, -3),
-            (1, 3, -2),
-            (-4, -6, 5),
-            (7, 0, 8)
-        ])));
-        assert!(all_orientations.contains(&Scanner::new(&[
-            (1, 1, 1),
-            (2, 2, 2),
-            (3, 3, 3),
-            (3, 1, 2),
-            (-6, -4, -5),
-            (0, 7, -8)
-        ])));
-        assert!(all_orientations.contains(&Scanner::new(&[
-            (-1, -1, 1),
-            (-2, -2, 2),
-            (-3, -3, 3),
-            (-2, -3, 1),
-            (5, 6, -4),
-            (8, 0, 7)
-        ])));
-        assert!(all_orientations.contains(&Scanner::new(&[
-            (-1, -1, 1),
-            (-2, -2, 2),
-            (-3, -3, 3),
-            (-2, -3, 1),
-            (5, 6, -4),
-            (8, 0, 7)
-        ])));
+        let all_orientations: Vec<Vec<Pos>> = proper_rotations()
+            .iter()
+            .map(|rotation| {
+                scanner
+                    .beacons
+                    .iter()
+                    .map(|b| rotate(rotation, b))
+                    .collect()
+            })
+            .collect();
+        assert!(all_orientations.contains(&vec![
+            [1, -1, 1],
+            [2, -2, 2],
+            [3, -3, 3],
+            [2, -1, 3],
+            [-5, 4, -6],
+            [-8, -7, 0]
+        ]));
+        assert!(all_orientations.contains(&vec![
+            [-1, -1, -1],
+            [-2, -2, -2],
+            [-3, -3, -3],
+            [-1, -3, -2],
+            [4, 6, 5],
+            [-7, 0, 8]
+        ]));
+        assert!(all_orientations.contains(&vec![
+            [1, 1, -1],
+            [2, 2, -2],
+            [3, 3, -3],
+            [1, 3, -2],
+            [-4, -6, 5],
+            [7, 0, 8]
+        ]));
+        assert!(all_orientations.contains(&vec![
+            [1, 1, 1],
+            [2, 2, 2],
+            [3, 3, 3],
+            [3, 1, 2],
+            [-6, -4, -5],
+            [0, 7, -8]
+        ]));
+        assert!(all_orientations.contains(&vec![
+            [-1, -1, 1],
+            [-2, -2, 2],
+            [-3, -3, 3],
+            [-2, -3, 1],
+            [5, 6, -4],
+            [8, 0, 7]
+        ]));
+    }
+
+    #[test]
+    fn test_transform() {
+        let rotations = proper_rotations();
+        let a = Transform {
+            rotation: rotations[5],
+            translation: [1, -2, 3],
+        };
+        let b = Transform {
+            rotation: rotations[17],
+            translation: [-40, 50, 60],
+        };
+        let p = [7, 11, -13];
+        assert_eq!(a.compose(&b).apply(&p), a.apply(&b.apply(&p)));
+        assert_eq!(a.inverse().apply(&a.apply(&p)), p);
+        assert_eq!(a.compose(&a.inverse()), Transform::identity());
+    }
+
+    #[test]
+    fn test_proper_rotations_in_other_dimensions() {
+        let rotations: Vec<Rotation<2>> = proper_rotations();
+        assert_eq!(
+            HashSet::<Rotation<2>>::from_iter(rotations.iter().cloned()),
+            HashSet::from_iter(
+                [
+                    [[1, 0], [0, 1]],
+                    [[0, -1], [1, 0]],
+                    [[-1, 0], [0, -1]],
+                    [[0, 1], [-1, 0]],
+                ]
+                .iter()
+                .cloned()
+            )
+        );
+        assert_eq!(proper_rotations::<1>(), vec![[[1]]]);
+        let rotations: Vec<Rotation<4>> = proper_rotations();
+        assert_eq!(rotations.len(), 192);
+        assert_eq!(
+            HashSet::<Rotation<4>>::from_iter(rotations.iter().cloned()).len(),
+            192
+        );
+    }
+
+    #[test]
+    fn test_parsing_2d() {
+        let mut input: &[u8] = b"--- scanner 0 ---
+0,2
+4,1
+
+--- scanner 1 ---
+-1,-1";
+        let scanners: Vec<Scanner<2>> = parse_input(&mut input).unwrap();
+        assert_eq!(
+            scanners,
+            vec![Scanner::new(&[[0, 2], [4, 1]]), Scanner::new(&[[-1, -1]])]
+        );
+
+        let mut input: &[u8] = b"--- scanner 0 ---
+0,2,0";
+        assert!(parse_input::<_, 2>(&mut input).is_err());
+    }
+
+    #[test]
+    fn test_build_map_2d() {
+        let beacons: Vec<Pos<2>> = vec![
+            [3, 7],
+            [-42, 15],
+            [27, -31],
+            [-8, -55],
+            [61, 12],
+            [14, 48],
+            [-67, -9],
+            [39, 70],
+            [120, -14],
+            [95, 33],
+        ];
+        let transform = Transform {
+            rotation: [[0, -1], [1, 0]],
+            translation: [100, -50],
+        };
+        let local_beacons: Vec<Pos<2>> = beacons[2..]
+            .iter()
+            .map(|b| transform.inverse().apply(b))
+            .collect();
+        let scanners = vec![Scanner::new(&beacons[..8]), Scanner::new(&local_beacons)];
+        let config = AlignmentConfig {
+            min_overlap: 6,
+            ..AlignmentConfig::default()
+        };
+        let (map, unaligned) = build_map(&scanners, config);
+        assert!(unaligned.is_empty());
+        assert_eq!(map.scanners[&1], transform);
+        assert_eq!(map.beacons, HashSet::from_iter(beacons.iter().cloned()));
+        assert_eq!(map.max_scanner_manhatten_dist(), 150);
+    }
+
+    #[test]
+    fn test_build_map_without_shared_fingerprints() {
+        // Each shared beacon has different close neighbors in both scanners,
+        // so no fingerprints match and only the exhaustive search aligns them.
+        let shared: Vec<Pos<2>> = (0..12).map(|i| [1000 * i, 37 * i * i]).collect();
+        let with_neighbors = |offsets: fn(i64) -> [Pos<2>; 2]| -> Vec<Pos<2>> {
+            shared
+                .iter()
+                .zip(0..)
+                .flat_map(|(b, i)| {
+                    std::iter::once(*b).chain(offsets(i).map(|offset| add(b, &offset)))
+                })
+                .collect()
+        };
+        let transform = Transform {
+            rotation: [[0, 1], [-1, 0]],
+            translation: [-300, 4000],
+        };
+        let local_beacons: Vec<Pos<2>> = with_neighbors(|i| [[0, -20 - i], [-20 - i, -3]])
+            .iter()
+            .map(|b| transform.inverse().apply(b))
+            .collect();
+        let scanners = vec![
+            Scanner::new(&with_neighbors(|i| [[i + 1, 0], [0, i + 1]])),
+            Scanner::new(&local_beacons),
+        ];
+        let (map, unaligned) = build_map(&scanners, AlignmentConfig::default());
+        assert!(unaligned.is_empty());
+        assert_eq!(map.scanners[&1], transform);
+        assert_eq!(map.beacons.len(), 12 * 5);
     }
 
     #[test]
     fn test_build_map() {
         let mut input: &[u8] = include_bytes!("../test.input");
         let scanners = parse_input(&mut input).unwrap();
-        let map = build_map(&scanners);
+        let (map, unaligned) = build_map(&scanners, AlignmentConfig::default());
+        assert!(unaligned.is_empty());
+        let positions: Vec<Option<Pos<3>>> = (0..scanners.len())
+            .map(|index| {
+                map.relative_transform(index, 0)
+                    .map(|transform| transform.translation)
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                Some([0, 0, 0]),
+                Some([68, -1246, -43]),
+                Some([1105, -1205, 1229]),
+                Some([-92, -2380, -20]),
+                Some([-20, -1133, 1061]),
+            ]
+        );
         assert_eq!(
             map.beacons,
             HashSet::from_iter(
                 [
-                    (-892, 524, 684),
-                    (-876, 649, 763),
-                    (-838, 591, 734),
-                    (-789, 900, -551),
-                    (-739, -1745, 668),
-                    (-706, -3180, -659),
-                    (-697, -3072, -689),
-                    (-689, 845, -530),
-                    (-687, -1600, 576),
-                    (-661, -816, -575),
-                    (-654, -3158, -753),
-                    (-635, -1737, 486),
-                    (-631, -672, 1502),
-                    (-624, -1620, 1868),
-                    (-620, -3212, 371),
-                    (-618, -824, -621),
-                    (-612, -1695, 1788),
-                    (-601, -1648, -643),
-                    (-584, 868, -557),
-                    (-537, -823, -458),
-                    (-532, -1715, 1894),
-                    (-518, -1681, -600),
-                    (-499, -1607, -770),
-                    (-485, -357, 347),
-                    (-470, -3283, 303),
-                    (-456, -621, 1527),
-                    (-447, -329, 318),
-                    (-430, -3130, 366),
-                    (-413, -627, 1469),
-                    (-345, -311, 381),
-                    (-36, -1284, 1171),
-                    (-27, -1108, -65),
-                    (7, -33, -71),
-                    (12, -2351, -103),
-                    (26, -1119, 1091),
-                    (346, -2985, 342),
-                    (366, -3059, 397),
-                    (377, -2827, 367),
-                    (390, -675, -793),
-                    (396, -1931, -563),
-                    (404, -588, -901),
-                    (408, -1815, 803),
-                    (423, -701, 434),
-                    (432, -2009, 850),
-                    (443, 580, 662),
-                    (455, 729, 728),
-                    (456, -540, 1869),
-                    (459, -707, 401),
-                    (465, -695, 1988),
-                    (474, 580, 667),
-                    (496, -1584, 1900),
-                    (497, -1838, -617),
-                    (527, -524, 1933),
-                    (528, -643, 409),
-                    (534, -1912, 768),
-                    (544, -627, -890),
-                    (553, 345, -567),
-                    (564, 392, -477),
-                    (568, -2007, -577),
-                    (605, -1665, 1952),
-                    (612, -1593, 1893),
-                    (630, 319, -379),
-                    (686, -3108, -505),
-                    (776, -3184, -501),
-                    (846, -3110, -434),
-                    (1135, -1161, 1235),
-                    (1243, -1093, 1063),
-                    (1660, -552, 429),
-                    (1693, -557, 386),
-                    (1735, -437, 1738),
-                    (1749, -1800, 1813),
-                    (1772, -405, 1572),
-                    (1776, -675, 371),
-                    (1779, -442, 1789),
-                    (1780, -1548, 337),
-                    (1786, -1538, 337),
-                    (1847, -1591, 415),
-                    (1889, -1729, 1762),
-                    (1994, -1805, 1792),
+                    [-892, 524, 684],
+                    [-876, 649, 763],
+                    [-838, 591, 734],
+                    [-789, 900, -551],
+                    [-739, -1745, 668],
+                    [-706, -3180, -659],
+                    [-697, -3072, -689],
+                    [-689, 845, -530],
+                    [-687, -1600, 576],
+                    [-661, -816, -575],
+                    [-654, -3158, -753],
+                    [-635, -1737, 486],
+                    [-631, -672, 1502],
+                    [-624, -1620, 1868],
+                    [-620, -3212, 371],
+                    [-618, -824, -621],
+                    [-612, -1695, 1788],
+                    [-601, -1648, -643],
+                    [-584, 868, -557],
+                    [-537, -823, -458],
+                    [-532, -1715, 1894],
+                    [-518, -1681, -600],
+                    [-499, -1607, -770],
+                    [-485, -357, 347],
+                    [-470, -3283, 303],
+                    [-456, -621, 1527],
+                    [-447, -329, 318],
+                    [-430, -3130, 366],
+                    [-413, -627, 1469],
+                    [-345, -311, 381],
+                    [-36, -1284, 1171],
+                    [-27, -1108, -65],
+                    [7, -33, -71],
+                    [12, -2351, -103],
+                    [26, -1119, 1091],
+                    [346, -2985, 342],
+                    [366, -3059, 397],
+                    [377, -2827, 367],
+                    [390, -675, -793],
+                    [396, -1931, -563],
+                    [404, -588, -901],
+                    [408, -1815, 803],
+                    [423, -701, 434],
+                    [432, -2009, 850],
+                    [443, 580, 662],
+                    [455, 729, 728],
+                    [456, -540, 1869],
+                    [459, -707, 401],
+                    [465, -695, 1988],
+                    [474, 580, 667],
+                    [496, -1584, 1900],
+                    [497, -1838, -617],
+                    [527, -524, 1933],
+                    [528, -643, 409],
+                    [534, -1912, 768],
+                    [544, -627, -890],
+                    [553, 345, -567],
+                    [564, 392, -477],
+                    [568, -2007, -577],
+                    [605, -1665, 1952],
+                    [612, -1593, 1893],
+                    [630, 319, -379],
+                    [686, -3108, -505],
+                    [776, -3184, -501],
+                    [846, -3110, -434],
+                    [1135, -1161, 1235],
+                    [1243, -1093, 1063],
+                    [1660, -552, 429],
+                    [1693, -557, 386],
+                    [1735, -437, 1738],
+                    [1749, -1800, 1813],
+                    [1772, -405, 1572],
+                    [1776, -675, 371],
+                    [1779, -442, 1789],
+                    [1780, -1548, 337],
+                    [1786, -1538, 337],
+                    [1847, -1591, 415],
+                    [1889, -1729, 1762],
+                    [1994, -1805, 1792],
                 ]
                 .iter()
                 .cloned()
             )
         );
         assert_eq!(
-            map.scanners,
+            map.scanner_positions(),
             HashSet::from_iter(
                 [
-                    (0, 0, 0),
-                    (68, -1246, -43),
-                    (1105, -1205, 1229),
-                    (-92, -2380, -20),
-                    (-20, -1133, 1061)
+                    [0, 0, 0],
+                    [68, -1246, -43],
+                    [1105, -1205, 1229],
+                    [-92, -2380, -20],
+                    [-20, -1133, 1061]
                 ]
                 .iter()
                 .cloned()
             )
         );
+        assert_eq!(
+            map.scanners[&1],
+            Transform {
+                rotation: [[-1, 0, 0], [0, 1, 0], [0, 0, -1]],
+                translation: [68, -1246, -43],
+            }
+        );
+        assert_eq!(
+            map.relative_transform(4, 1).unwrap().translation,
+            [88, 113, -1104]
+        );
         assert_eq!(map.max_scanner_manhatten_dist(), 3621);
     }
+
+    #[test]
+    fn test_build_map_tolerant() {
+        let mut input: &[u8] = include_bytes!("../test.input");
+        let scanners: Vec<Scanner> = parse_input(&mut input)
+            .unwrap()
+            .iter()
+            .map(|scanner: &Scanner| {
+                let noisy_beacons: Vec<Pos> = scanner
+                    .beacons
+                    .iter()
+                    .enumerate()
+                    .skip(1)
+                    .map(|(i, b)| [b[0] + (i % 3) as i64 - 1, b[1] - (i % 2) as i64, b[2]])
+                    .collect();
+                Scanner::new(&noisy_beacons)
+            })
+            .collect();
+        let config = AlignmentConfig {
+            min_overlap: 10,
+            matching: Matching::Tolerant(2),
+            ..AlignmentConfig::default()
+        };
+        let (map, unaligned) = build_map(&scanners, config);
+        assert!(unaligned.is_empty());
+        let expected_positions = [
+            [0, 0, 0],
+            [68, -1246, -43],
+            [1105, -1205, 1229],
+            [-92, -2380, -20],
+            [-20, -1133, 1061],
+        ];
+        for (index, expected) in expected_positions.iter().enumerate() {
+            assert!(chebyshev_dist(&map.scanners[&index].translation, expected) <= 2);
+            assert!(map.confidences[&index] > 0.4);
+        }
+        assert_eq!(
+            map.scanners[&1].rotation,
+            [[-1, 0, 0], [0, 1, 0], [0, 0, -1]]
+        );
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = [
+            "--min-overlap",
+            "6",
+            "--tolerance",
+            "3",
+            "--export",
+            "map.ply",
+            "--dimensions",
+            "2",
+        ]
+        .iter()
+        .map(|arg| arg.to_string());
+        assert_eq!(
+            parse_args(args).unwrap(),
+            Options {
+                config: AlignmentConfig {
+                    min_overlap: 6,
+                    matching: Matching::Tolerant(3),
+                    ..AlignmentConfig::default()
+                },
+                clusters: false,
+                exports: vec!["map.ply".to_string()],
+                dimensions: 2,
+            }
+        );
+    }
+
+    fn scaled(scanner: &Scanner, factor: i64) -> Scanner {
+        let beacons: Vec<Pos> = scanner
+            .beacons
+            .iter()
+            .map(|b| b.map(|c| c * factor))
+            .collect();
+        Scanner::new(&beacons)
+    }
+
+    #[test]
+    fn test_build_map_with_unalignable_scanner() {
+        let mut input: &[u8] = include_bytes!("../test.input");
+        let mut scanners = parse_input(&mut input).unwrap();
+        scanners.insert(2, scaled(&scanners[0], 3));
+        let (map, unaligned) = build_map(&scanners, AlignmentConfig::default());
+        assert_eq!(unaligned, vec![2]);
+        assert_eq!(map.scanners.len(), 5);
+        assert_eq!(map.beacons.len(), 79);
+    }
+
+    #[test]
+    fn test_build_clusters() {
+        let mut input: &[u8] = include_bytes!("../test.input");
+        let mut scanners = parse_input(&mut input).unwrap();
+        scanners.push(scaled(&scanners[0], 2));
+        scanners.push(scaled(&scanners[1], 2));
+        scanners.push(scaled(&scanners[2], 3));
+        let clusters = build_clusters(&scanners, AlignmentConfig::default());
+        let cluster_indices: Vec<HashSet<usize>> = clusters
+            .iter()
+            .map(|map| map.scanners.keys().copied().collect())
+            .collect();
+        assert_eq!(
+            cluster_indices,
+            vec![
+                HashSet::from_iter(0..5),
+                HashSet::from_iter(5..7),
+                HashSet::from_iter(7..8)
+            ]
+        );
+        assert_eq!(
+            clusters[1].relative_transform(6, 5).unwrap().translation,
+            [136, -2492, -86]
+        );
+    }
 }
@@ -0,0 +1,334 @@
+use std::error::Error;
+use std::io::{self, Write};
+
+use super::{bounding_box, Cuboid, ParseError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn from_path(path: &str) -> Result<Self, Box<dyn Error>> {
+        match path.rsplit('.').next() {
+            Some("csv") => Ok(Format::Csv),
+            Some("json") => Ok(Format::Json),
+            _ => Err(Box::new(ParseError)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    fn name(self) -> &'static str {
+        match self {
+            Axis::X => "x",
+            Axis::Y => "y",
+            Axis::Z => "z",
+        }
+    }
+
+    fn range(self, cuboid: &Cuboid) -> (isize, isize) {
+        match self {
+            Axis::X => cuboid.x_range,
+            Axis::Y => cuboid.y_range,
+            Axis::Z => cuboid.z_range,
+        }
+    }
+
+    fn with_range(self, cuboid: &Cuboid, range: (isize, isize)) -> Cuboid {
+        let mut cuboid = cuboid.clone();
+        match self {
+            Axis::X => cuboid.x_range = range,
+            Axis::Y => cuboid.y_range = range,
+            Axis::Z => cuboid.z_range = range,
+        }
+        cuboid
+    }
+
+    // The axes spanning the plane orthogonal to this one, as columns and rows.
+    fn plane_axes(self) -> (Axis, Axis) {
+        match self {
+            Axis::X => (Axis::Y, Axis::Z),
+            Axis::Y => (Axis::X, Axis::Z),
+            Axis::Z => (Axis::X, Axis::Y),
+        }
+    }
+}
+
+// Parses a plane like `z=10`.
+pub fn parse_plane(input: &str) -> Result<(Axis, isize), Box<dyn Error>> {
+    let (name, value) = input.split_once('=').ok_or(ParseError)?;
+    let axis = Axis::ALL
+        .iter()
+        .find(|axis| axis.name() == name.trim())
+        .ok_or(ParseError)?;
+    Ok((*axis, value.trim().parse()?))
+}
+
+// Joins cuboids that touch along one axis and have the same extent along the
+// other two until no more cuboids can be joined.
+pub fn merge(mut cuboids: Vec<Cuboid>) -> Vec<Cuboid> {
+    loop {
+        let num_cuboids = cuboids.len();
+        for axis in Axis::ALL {
+            let (a, b) = axis.plane_axes();
+            cuboids.sort_unstable_by_key(|c| (a.range(c), b.range(c), axis.range(c)));
+            let mut merged: Vec<Cuboid> = Vec::with_capacity(cuboids.len());
+            for cuboid in cuboids {
+                match merged.last_mut() {
+                    Some(last)
+                        if a.range(last) == a.range(&cuboid)
+                            && b.range(last) == b.range(&cuboid)
+                            && axis.range(last).1 == axis.range(&cuboid).0 =>
+                    {
+                        *last = axis.with_range(last, (axis.range(last).0, axis.range(&cuboid).1));
+                    }
+                    _ => merged.push(cuboid),
+                }
+            }
+            cuboids = merged;
+        }
+        if cuboids.len() == num_cuboids {
+            cuboids.sort_unstable_by_key(|c| (c.x_range, c.y_range, c.z_range));
+            return cuboids;
+        }
+    }
+}
+
+pub fn write_csv<W: Write>(cuboids: &[Cuboid], out: &mut W) -> io::Result<()> {
+    writeln!(out, "x_min,x_max,y_min,y_max,z_min,z_max,cubes")?;
+    for cuboid in cuboids {
+        let bounds: Vec<String> = Axis::ALL
+            .iter()
+            .map(|axis| {
+                let range = axis.range(cuboid);
+                format!("{},{}", range.0, range.1 - 1)
+            })
+            .collect();
+        writeln!(out, "{},{}", bounds.join(","), cuboid.num_cubes())?;
+    }
+    Ok(())
+}
+
+pub fn write_json<W: Write>(cuboids: &[Cuboid], out: &mut W) -> io::Result<()> {
+    let cuboids: Vec<String> = cuboids
+        .iter()
+        .map(|cuboid| {
+            let bounds: Vec<String> = Axis::ALL
+                .iter()
+                .map(|axis| {
+                    let range = axis.range(cuboid);
+                    format!("\"{}\": [{}, {}]", axis.name(), range.0, range.1 - 1)
+                })
+                .collect();
+            format!("{{{}}}", bounds.join(", "))
+        })
+        .collect();
+    writeln!(out, "[")?;
+    if !cuboids.is_empty() {
+        writeln!(out, "  {}", cuboids.join(",\n  "))?;
+    }
+    writeln!(out, "]")
+}
+
+pub fn write<W: Write>(cuboids: &[Cuboid], format: Format, out: &mut W) -> io::Result<()> {
+    match format {
+        Format::Csv => write_csv(cuboids, out),
+        Format::Json => write_json(cuboids, out),
+    }
+}
+
+// Cross-sections with more cells are rejected instead of printed.
+pub const MAX_SLICE_CELLS: usize = 1 << 20;
+
+// Draws the cross-section at `axis = value` with `#` for lit and `.` for unlit
+// cubes. Without `bounds`, the section is cropped to the lit cubes.
+pub fn render_slice<W: Write>(
+    cuboids: &[Cuboid],
+    axis: Axis,
+    value: isize,
+    bounds: Option<&Cuboid>,
+    out: &mut W,
+) -> io::Result<()> {
+    let everything = match bounds.cloned().or_else(|| bounding_box(cuboids)) {
+        Some(bounds) => bounds,
+        None => return writeln!(out, "{}={}: nothing on", axis.name(), value),
+    };
+    let plane = axis.with_range(&everything, (value, value + 1));
+    let section: Vec<Cuboid> = cuboids.iter().filter_map(|c| c.intersect(&plane)).collect();
+    let bounds = match bounds {
+        Some(_) => Some(plane),
+        None => bounding_box(&section),
+    };
+    let bounds = match bounds {
+        Some(bounds) => bounds,
+        None => return writeln!(out, "{}={}: nothing on", axis.name(), value),
+    };
+
+    let (column_axis, row_axis) = axis.plane_axes();
+    let (columns, rows) = (column_axis.range(&bounds), row_axis.range(&bounds));
+    let (width, height) = ((columns.1 - columns.0) as usize, (rows.1 - rows.0) as usize);
+    if width.saturating_mul(height) > MAX_SLICE_CELLS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "cross-section of {}x{} cubes is too large, limit it with --slice-region",
+                width, height
+            ),
+        ));
+    }
+    writeln!(
+        out,
+        "{}={}, {}={}..{}, {}={}..{}",
+        axis.name(),
+        value,
+        column_axis.name(),
+        columns.0,
+        columns.1 - 1,
+        row_axis.name(),
+        rows.0,
+        rows.1 - 1
+    )?;
+    for row in rows.0..rows.1 {
+        let line: String = (columns.0..columns.1)
+            .map(|column| {
+                let lit = section.iter().any(|c| {
+                    let (start, end) = column_axis.range(c);
+                    let (row_start, row_end) = row_axis.range(c);
+                    start <= column && column < end && row_start <= row && row < row_end
+                });
+                if lit {
+                    '#'
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cuboid(x_range: (isize, isize), y_range: (isize, isize), z_range: (isize, isize)) -> Cuboid {
+        Cuboid {
+            x_range,
+            y_range,
+            z_range,
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let unit_cubes: Vec<Cuboid> = (0..8)
+            .map(|i| Cuboid::point((i & 1, (i >> 1) & 1, i >> 2)))
+            .collect();
+        assert_eq!(merge(unit_cubes), vec![cuboid((0, 2), (0, 2), (0, 2))]);
+
+        let l_shape = vec![
+            cuboid((0, 1), (0, 1), (0, 1)),
+            cuboid((1, 2), (0, 1), (0, 1)),
+            cuboid((0, 1), (1, 2), (0, 1)),
+        ];
+        assert_eq!(merge(l_shape).len(), 2);
+
+        let apart = vec![
+            cuboid((0, 1), (0, 1), (0, 1)),
+            cuboid((2, 3), (0, 1), (0, 1)),
+        ];
+        assert_eq!(merge(apart.clone()), apart);
+    }
+
+    #[test]
+    fn test_write() {
+        let cuboids = vec![
+            cuboid((-1, 2), (0, 1), (5, 7)),
+            cuboid((3, 4), (3, 4), (3, 4)),
+        ];
+        let mut out = vec![];
+        write_csv(&cuboids, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+x_min,x_max,y_min,y_max,z_min,z_max,cubes
+-1,1,0,0,5,6,6
+3,3,3,3,3,3,1
+"
+        );
+        let mut out = vec![];
+        write_json(&cuboids, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"[
+  {"x": [-1, 1], "y": [0, 0], "z": [5, 6]},
+  {"x": [3, 3], "y": [3, 3], "z": [3, 3]}
+]
+"#
+        );
+    }
+
+    #[test]
+    fn test_render_slice() {
+        let cuboids = vec![
+            cuboid((0, 3), (0, 2), (0, 1)),
+            cuboid((4, 5), (-1, 0), (-2, 2)),
+        ];
+        let mut out = vec![];
+        render_slice(&cuboids, Axis::Z, 0, None, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+z=0, x=0..4, y=-1..1
+....#
+###..
+###..
+"
+        );
+        let mut out = vec![];
+        render_slice(
+            &cuboids,
+            Axis::X,
+            4,
+            Some(&cuboid((0, 1), (-1, 1), (-3, 3))),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+x=4, y=-1..0, z=-3..2
+..
+#.
+#.
+#.
+#.
+..
+"
+        );
+        let mut out = vec![];
+        render_slice(&cuboids, Axis::Y, 7, None, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "y=7: nothing on\n");
+        let huge = vec![cuboid((0, 2000), (0, 1000), (0, 1))];
+        assert!(render_slice(&huge, Axis::Z, 0, None, &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn test_parse_plane() {
+        assert_eq!(parse_plane("z=-3").unwrap(), (Axis::Z, -3));
+        assert_eq!(parse_plane("x = 12").unwrap(), (Axis::X, 12));
+        assert!(parse_plane("w=1").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use super::{Engine, RebootStep};
+
+// Records the applied steps, the number of lit cubes after each step and
+// copies of the engine state. Earlier states are restored from the closest
+// snapshot before them by replaying the steps since.
+pub struct History<E: Engine> {
+    steps: Vec<RebootStep>,
+    counts: Vec<usize>,
+    snapshots: BTreeMap<usize, E>,
+    current: E,
+    checkpoint_interval: usize,
+}
+
+impl<E: Engine> History<E> {
+    // Takes a snapshot automatically every `checkpoint_interval` steps.
+    pub fn new(checkpoint_interval: usize) -> Self {
+        Self {
+            steps: vec![],
+            counts: vec![0],
+            snapshots: BTreeMap::from([(0, E::default())]),
+            current: E::default(),
+            checkpoint_interval: checkpoint_interval.max(1),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn current(&self) -> &E {
+        &self.current
+    }
+
+    pub fn apply(&mut self, step: &RebootStep) {
+        self.current.apply(step);
+        self.steps.push(step.clone());
+        self.counts.push(self.current.count_on());
+        if self.len().is_multiple_of(self.checkpoint_interval) {
+            self.snapshot();
+        }
+    }
+
+    pub fn snapshot(&mut self) {
+        self.snapshots.insert(self.len(), self.current.clone());
+    }
+
+    pub fn count_after(&self, k: usize) -> Option<usize> {
+        self.counts.get(k).copied()
+    }
+
+    // The first step after which `predicate` holds for the number of lit cubes.
+    pub fn first_step_where<P: Fn(usize) -> bool>(&self, predicate: P) -> Option<usize> {
+        self.counts.iter().position(|&count| predicate(count))
+    }
+
+    pub fn state_after(&self, k: usize) -> Option<E> {
+        if k > self.len() {
+            return None;
+        }
+        let (&start, snapshot) = self.snapshots.range(..=k).next_back()?;
+        let mut state = snapshot.clone();
+        for step in self.steps[start..k].iter() {
+            state.apply(step);
+        }
+        Some(state)
+    }
+
+    // Discards all steps after step `k`.
+    pub fn rollback(&mut self, k: usize) -> bool {
+        match self.state_after(k) {
+            Some(state) => {
+                self.current = state;
+                self.steps.truncate(k);
+                self.counts.truncate(k + 1);
+                self.snapshots.split_off(&(k + 1));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_steps, reboot, Action, Cuboid, InvertedRanges, Reactor, SignedReactor};
+
+    fn test_steps() -> Vec<RebootStep> {
+        let mut buf: &[u8] = include_bytes!("../test2.input");
+        parse_steps(&mut buf, InvertedRanges::Reject).unwrap()
+    }
+
+    fn test_history<E: Engine>() {
+        let steps = test_steps();
+        let mut history = History::<E>::new(7);
+        for step in steps.iter() {
+            history.apply(step);
+        }
+        assert_eq!(history.len(), steps.len());
+        for k in [0, 1, 6, 7, 8, 30, steps.len()] {
+            let expected = reboot::<E>(&steps[..k]).1;
+            assert_eq!(history.count_after(k), Some(expected));
+            assert_eq!(history.state_after(k).unwrap().count_on(), expected);
+        }
+        assert_eq!(history.count_after(steps.len() + 1), None);
+        assert!(history.state_after(steps.len() + 1).is_none());
+
+        assert!(history.rollback(10));
+        assert_eq!(history.len(), 10);
+        assert_eq!(history.current().count_on(), reboot::<E>(&steps[..10]).1);
+        history.snapshot();
+        for step in steps[20..].iter() {
+            history.apply(step);
+        }
+        let replayed: Vec<RebootStep> = steps[..10]
+            .iter()
+            .chain(steps[20..].iter())
+            .cloned()
+            .collect();
+        assert_eq!(
+            history.count_after(15),
+            Some(reboot::<E>(&replayed[..15]).1)
+        );
+        assert_eq!(history.current().count_on(), reboot::<E>(&replayed).1);
+        assert!(!history.rollback(replayed.len() + 1));
+    }
+
+    #[test]
+    fn test_history_subtract() {
+        test_history::<Reactor>();
+    }
+
+    #[test]
+    fn test_history_signed() {
+        test_history::<SignedReactor>();
+    }
+
+    #[test]
+    fn test_snapshots_independent_of_steps() {
+        let step = |action| RebootStep {
+            action,
+            cuboid: Cuboid::point((0, 0, 0)),
+        };
+        let mut history = History::<SignedReactor>::new(1);
+        for i in 0..1000 {
+            history.apply(&step(if i % 2 == 0 { Action::On } else { Action::Off }));
+        }
+        assert_eq!(history.snapshots.len(), 1001);
+        assert!(history
+            .snapshots
+            .values()
+            .all(|snapshot| snapshot.signed_cubes.len() <= 1));
+    }
+
+    #[test]
+    fn test_first_step_where() {
+        let steps = test_steps();
+        let mut history = History::<SignedReactor>::new(10);
+        for step in steps.iter() {
+            history.apply(step);
+        }
+        let k = history.first_step_where(|count| count > 1000000).unwrap();
+        assert!(reboot::<SignedReactor>(&steps[..k - 1]).1 <= 1000000);
+        assert!(reboot::<SignedReactor>(&steps[..k]).1 > 1000000);
+    }
+}
@@ -1,10 +1,14 @@
-#[macro_use]
-extern crate lazy_static;
+mod export;
+mod history;
 
-use std::io::{self, BufRead};
-use std::{error::Error, fmt::Display};
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter};
+use std::time::Instant;
 
-use regex::Regex;
+use history::History;
+use std::{error::Error, fmt::Display};
 
 #[derive(Debug)]
 struct ParseError;
@@ -18,13 +22,77 @@ impl Display for ParseError {
 impl Error for ParseError {}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+struct FieldError {
+    field: &'static str,
+    message: String,
+}
+
+impl FieldError {
+    fn new(field: &'static str, message: String) -> Self {
+        Self { field, message }
+    }
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl Error for FieldError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct StepError {
+    line: usize,
+    error: FieldError,
+}
+
+impl Display for StepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl Error for StepError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InvertedRanges {
+    Reject,
+    // Swaps the bounds, i.e. `x=10..5` becomes `x=5..10`.
+    Normalize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct Cuboid {
     x_range: (isize, isize),
     y_range: (isize, isize),
     z_range: (isize, isize),
 }
 
+fn bounding_box(cuboids: &[Cuboid]) -> Option<Cuboid> {
+    let union = |a: (isize, isize), b: (isize, isize)| (a.0.min(b.0), a.1.max(b.1));
+    cuboids.iter().cloned().reduce(|a, b| Cuboid {
+        x_range: union(a.x_range, b.x_range),
+        y_range: union(a.y_range, b.y_range),
+        z_range: union(a.z_range, b.z_range),
+    })
+}
+
+const INIT_REGION: Cuboid = Cuboid {
+    x_range: (-50, 51),
+    y_range: (-50, 51),
+    z_range: (-50, 51),
+};
+
 impl Cuboid {
+    fn point(p: (isize, isize, isize)) -> Self {
+        Self {
+            x_range: (p.0, p.0 + 1),
+            y_range: (p.1, p.1 + 1),
+            z_range: (p.2, p.2 + 1),
+        }
+    }
+
     fn subtract(&self, other: &Cuboid) -> Vec<Cuboid> {
         let x_cuts = [
             (self.x_range.0, self.x_range.1.min(other.x_range.0)),
@@ -104,6 +172,45 @@ impl Cuboid {
         cuboids
     }
 
+    fn intersect(&self, other: &Cuboid) -> Option<Cuboid> {
+        let intersect_range = |a: (isize, isize), b: (isize, isize)| {
+            let range = (a.0.max(b.0), a.1.min(b.1));
+            if range.1 > range.0 {
+                Some(range)
+            } else {
+                None
+            }
+        };
+        Some(Cuboid {
+            x_range: intersect_range(self.x_range, other.x_range)?,
+            y_range: intersect_range(self.y_range, other.y_range)?,
+            z_range: intersect_range(self.z_range, other.z_range)?,
+        })
+    }
+
+    // Parses `x=A..B,y=C..D,z=E..F` with inclusive bounds.
+    fn try_parse(input: &str, inverted_ranges: InvertedRanges) -> Result<Self, FieldError> {
+        let mut fields = input.split(',');
+        let mut range = |axis: &'static str| {
+            let field = fields
+                .next()
+                .ok_or_else(|| FieldError::new(axis, "missing".to_string()))?;
+            parse_range(axis, field.trim(), inverted_ranges)
+        };
+        let cuboid = Self {
+            x_range: range("x")?,
+            y_range: range("y")?,
+            z_range: range("z")?,
+        };
+        match fields.next() {
+            Some(field) => Err(FieldError::new(
+                "cuboid",
+                format!("unexpected field '{}'", field.trim()),
+            )),
+            None => Ok(cuboid),
+        }
+    }
+
     fn num_cubes(&self) -> usize {
         (self.x_range.1 - self.x_range.0) as usize
             * (self.y_range.1 - self.y_range.0) as usize
@@ -111,63 +218,125 @@ impl Cuboid {
     }
 }
 
+impl Display for Cuboid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "x={}..{},y={}..{},z={}..{}",
+            self.x_range.0,
+            self.x_range.1 - 1,
+            self.y_range.0,
+            self.y_range.1 - 1,
+            self.z_range.0,
+            self.z_range.1 - 1
+        )
+    }
+}
+
+fn parse_range(
+    axis: &'static str,
+    input: &str,
+    inverted_ranges: InvertedRanges,
+) -> Result<(isize, isize), FieldError> {
+    let error = |message: String| FieldError::new(axis, message);
+    let range = match input.split_once('=') {
+        Some((name, range)) if name.trim() == axis => range.trim(),
+        _ => return Err(error(format!("expected '{}=', found '{}'", axis, input))),
+    };
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| error(format!("expected 'start..end', found '{}'", range)))?;
+    let parse_bound = |bound: &str| {
+        bound
+            .trim()
+            .parse::<isize>()
+            .map_err(|_| error(format!("invalid number '{}'", bound)))
+    };
+    let (start, end) = (parse_bound(start)?, parse_bound(end)?);
+    if start <= end {
+        Ok((start, end + 1))
+    } else {
+        match inverted_ranges {
+            InvertedRanges::Reject => Err(error(format!("inverted range {}..{}", start, end))),
+            InvertedRanges::Normalize => Ok((end, start + 1)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 struct RebootStep {
-    is_on: bool,
+    action: Action,
     cuboid: Cuboid,
 }
 
 impl RebootStep {
-    fn try_parse(input: &str) -> Result<Self, Box<dyn Error>> {
-        lazy_static! {
-            static ref RE: Regex = Regex::new(
-                r"^(on|off)\s+x=(-?\d+)..(-?\d+),y=(-?\d+)..(-?\d+),z=(-?\d+)..(-?\d+)$"
-            )
-            .unwrap();
-        }
-
-        let captures = RE.captures(input).ok_or(ParseError)?;
-        let is_on = match captures.get(1).ok_or(ParseError)?.as_str() {
-            "on" => Ok(true),
-            "off" => Ok(false),
-            _ => Err(ParseError),
-        }?;
+    fn try_parse(input: &str, inverted_ranges: InvertedRanges) -> Result<Self, FieldError> {
+        let (action, cuboid) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        let action = match action {
+            "on" => Action::On,
+            "off" => Action::Off,
+            "toggle" => Action::Toggle,
+            _ => {
+                return Err(FieldError::new(
+                    "action",
+                    format!("expected 'on', 'off' or 'toggle', found '{}'", action),
+                ))
+            }
+        };
 
         Ok(Self {
-            is_on,
-            cuboid: Cuboid {
-                x_range: (
-                    isize::from_str_radix(captures.get(2).ok_or(ParseError)?.as_str(), 10)?,
-                    isize::from_str_radix(captures.get(3).ok_or(ParseError)?.as_str(), 10)? + 1,
-                ),
-                y_range: (
-                    isize::from_str_radix(captures.get(4).ok_or(ParseError)?.as_str(), 10)?,
-                    isize::from_str_radix(captures.get(5).ok_or(ParseError)?.as_str(), 10)? + 1,
-                ),
-                z_range: (
-                    isize::from_str_radix(captures.get(6).ok_or(ParseError)?.as_str(), 10)?,
-                    isize::from_str_radix(captures.get(7).ok_or(ParseError)?.as_str(), 10)? + 1,
-                ),
-            },
+            action,
+            cuboid: Cuboid::try_parse(cuboid.trim_start(), inverted_ranges)?,
         })
     }
 }
 
+trait Engine: Default + Clone {
+    fn apply(&mut self, reboot_step: &RebootStep);
+    fn count_on(&self) -> usize;
+    fn count_on_in(&self, region: &Cuboid) -> usize;
+    // Disjoint cuboids covering exactly the lit cubes within `region`.
+    fn on_cuboids_in(&self, region: &Cuboid) -> Vec<Cuboid>;
+    // Disjoint cuboids covering exactly the lit cubes.
+    fn on_cuboids(&self) -> Vec<Cuboid>;
+
+    fn is_on(&self, p: (isize, isize, isize)) -> bool {
+        self.count_on_in(&Cuboid::point(p)) > 0
+    }
+}
+
+// Keeps a list of disjoint on-cuboids.
+#[derive(Clone, Default)]
 struct Reactor {
     on_cubes: Vec<Cuboid>,
 }
 
-impl Reactor {
-    fn new() -> Self {
-        Self { on_cubes: vec![] }
-    }
-
+impl Engine for Reactor {
     fn apply(&mut self, reboot_step: &RebootStep) {
         let mut new_on_cubes = Vec::with_capacity(self.on_cubes.len() * 6);
         for cuboid in self.on_cubes.iter() {
             new_on_cubes.extend(cuboid.subtract(&reboot_step.cuboid));
         }
-        if reboot_step.is_on {
-            new_on_cubes.push(reboot_step.cuboid.clone());
+        match reboot_step.action {
+            Action::On => new_on_cubes.push(reboot_step.cuboid.clone()),
+            Action::Off => {}
+            Action::Toggle => {
+                let mut previously_off = vec![reboot_step.cuboid.clone()];
+                for cuboid in self.on_cubes.iter() {
+                    previously_off = previously_off
+                        .iter()
+                        .flat_map(|c| c.subtract(cuboid))
+                        .collect();
+                }
+                new_on_cubes.extend(previously_off);
+            }
         }
         self.on_cubes = new_on_cubes;
     }
@@ -175,33 +344,400 @@ impl Reactor {
     fn count_on(&self) -> usize {
         self.on_cubes.iter().map(|c| c.num_cubes()).sum()
     }
+
+    fn count_on_in(&self, region: &Cuboid) -> usize {
+        self.on_cuboids_in(region)
+            .iter()
+            .map(|c| c.num_cubes())
+            .sum()
+    }
+
+    fn on_cuboids(&self) -> Vec<Cuboid> {
+        self.on_cubes.clone()
+    }
+
+    fn on_cuboids_in(&self, region: &Cuboid) -> Vec<Cuboid> {
+        self.on_cubes
+            .iter()
+            .filter_map(|c| c.intersect(region))
+            .collect()
+    }
 }
 
-fn reboot_reactor<R: BufRead>(reader: &mut R) -> Result<(usize, usize), Box<dyn Error>> {
-    let mut reactor_part1 = Reactor::new();
-    let mut reactor_part2 = Reactor::new();
-    for step in reader.lines().map(|line| RebootStep::try_parse(&line?)) {
-        let step = step?;
-        if -50 <= step.cuboid.x_range.0
-            && step.cuboid.x_range.1 <= 51
-            && -50 <= step.cuboid.y_range.0
-            && step.cuboid.y_range.1 <= 51
-            && -50 <= step.cuboid.z_range.0
-            && step.cuboid.z_range.1 <= 51
-        {
-            reactor_part1.apply(&step);
+// Inclusion-exclusion: every step cancels its intersections with all cuboids
+// counted so far. Identical cuboids are merged by summing their signs, which
+// keeps the list small when many steps overlap. A toggle cancels the
+// intersections twice to turn them off and adds the whole cuboid.
+#[derive(Clone, Default)]
+struct SignedReactor {
+    signed_cubes: HashMap<Cuboid, isize>,
+}
+
+type SignedCuboid<'a> = (&'a Cuboid, isize);
+
+// Splits along all bounds of the cuboids' `range` and describes the layer
+// between two consecutive bounds by `layer` of the cuboids spanning it.
+// Adjacent layers with the same description are joined, empty ones dropped.
+fn sweep<T: PartialEq, F: Fn(&[SignedCuboid]) -> Vec<T>>(
+    cuboids: &[SignedCuboid],
+    range: fn(&Cuboid) -> (isize, isize),
+    layer: F,
+) -> Vec<((isize, isize), Vec<T>)> {
+    let mut bounds: Vec<isize> = cuboids
+        .iter()
+        .flat_map(|(cuboid, _)| [range(cuboid).0, range(cuboid).1])
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+    let mut layers: Vec<((isize, isize), Vec<T>)> = vec![];
+    for bound in bounds.windows(2) {
+        let (start, end) = (bound[0], bound[1]);
+        let spanning: Vec<SignedCuboid> = cuboids
+            .iter()
+            .copied()
+            .filter(|(cuboid, _)| range(cuboid).0 <= start && end <= range(cuboid).1)
+            .collect();
+        let description = layer(&spanning);
+        match layers.last_mut() {
+            Some((range, last)) if range.1 == start && *last == description => range.1 = end,
+            _ if description.is_empty() => {}
+            _ => layers.push(((start, end), description)),
+        }
+    }
+    layers
+}
+
+// Disjoint cuboids covering the cells of the coordinate grid spanned by the
+// signed cuboids whose signs sum up to one.
+fn lit_cuboids(signed_cuboids: &[SignedCuboid]) -> Vec<Cuboid> {
+    let z_runs = |cuboids: &[SignedCuboid]| -> Vec<(isize, isize)> {
+        sweep(
+            cuboids,
+            |c| c.z_range,
+            |cuboids| {
+                let sign: isize = cuboids.iter().map(|(_, sign)| sign).sum();
+                if sign > 0 {
+                    vec![()]
+                } else {
+                    vec![]
+                }
+            },
+        )
+        .into_iter()
+        .map(|(z_range, _)| z_range)
+        .collect()
+    };
+    let mut lit = vec![];
+    for (x_range, rows) in sweep(
+        signed_cuboids,
+        |c| c.x_range,
+        |cuboids| sweep(cuboids, |c| c.y_range, z_runs),
+    ) {
+        for (y_range, z_ranges) in rows {
+            lit.extend(z_ranges.into_iter().map(|z_range| Cuboid {
+                x_range,
+                y_range,
+                z_range,
+            }));
+        }
+    }
+    lit
+}
+
+impl Engine for SignedReactor {
+    fn apply(&mut self, reboot_step: &RebootStep) {
+        let factor = match reboot_step.action {
+            Action::On | Action::Off => 1,
+            Action::Toggle => 2,
+        };
+        let mut updates: HashMap<Cuboid, isize> = HashMap::new();
+        for (cuboid, sign) in self.signed_cubes.iter() {
+            if let Some(intersection) = cuboid.intersect(&reboot_step.cuboid) {
+                *updates.entry(intersection).or_default() -= factor * sign;
+            }
+        }
+        if reboot_step.action != Action::Off {
+            *updates.entry(reboot_step.cuboid.clone()).or_default() += 1;
+        }
+        for (cuboid, sign) in updates {
+            *self.signed_cubes.entry(cuboid).or_default() += sign;
+        }
+        self.signed_cubes.retain(|_, sign| *sign != 0);
+    }
+
+    fn count_on(&self) -> usize {
+        self.signed_cubes
+            .iter()
+            .map(|(cuboid, sign)| sign * cuboid.num_cubes() as isize)
+            .sum::<isize>() as usize
+    }
+
+    fn count_on_in(&self, region: &Cuboid) -> usize {
+        self.signed_cubes
+            .iter()
+            .filter_map(|(cuboid, sign)| {
+                Some(sign * cuboid.intersect(region)?.num_cubes() as isize)
+            })
+            .sum::<isize>() as usize
+    }
+
+    fn on_cuboids(&self) -> Vec<Cuboid> {
+        let signed_cuboids: Vec<SignedCuboid> = self
+            .signed_cubes
+            .iter()
+            .map(|(cuboid, &sign)| (cuboid, sign))
+            .collect();
+        lit_cuboids(&signed_cuboids)
+    }
+
+    fn on_cuboids_in(&self, region: &Cuboid) -> Vec<Cuboid> {
+        let clipped: Vec<(Cuboid, isize)> = self
+            .signed_cubes
+            .iter()
+            .filter_map(|(cuboid, &sign)| Some((cuboid.intersect(region)?, sign)))
+            .collect();
+        let signed_cuboids: Vec<SignedCuboid> = clipped
+            .iter()
+            .map(|(cuboid, sign)| (cuboid, *sign))
+            .collect();
+        lit_cuboids(&signed_cuboids)
+    }
+}
+
+// Skips empty lines and everything after a `#`.
+fn parse_steps<R: BufRead>(
+    reader: &mut R,
+    inverted_ranges: InvertedRanges,
+) -> Result<Vec<RebootStep>, Box<dyn Error>> {
+    let mut steps = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let content = line.split('#').next().unwrap_or_default().trim();
+        if content.is_empty() {
+            continue;
+        }
+        let step = RebootStep::try_parse(content, inverted_ranges)
+            .map_err(|error| StepError { line: i + 1, error })?;
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+fn reboot<E: Engine>(steps: &[RebootStep]) -> (usize, usize) {
+    let mut reactor = E::default();
+    for step in steps {
+        reactor.apply(step)
+    }
+    (reactor.count_on_in(&INIT_REGION), reactor.count_on())
+}
+
+fn timed_reboot<E: Engine>(name: &str, steps: &[RebootStep]) -> (usize, usize) {
+    let start = Instant::now();
+    let result = reboot::<E>(steps);
+    println!("{}: {:?}", name, start.elapsed());
+    result
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Query {
+    Count(Cuboid),
+    IsOn((isize, isize, isize)),
+    List(Cuboid),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    engine: String,
+    queries: Vec<Query>,
+    inverted_ranges: InvertedRanges,
+    trace: bool,
+    after: Option<usize>,
+    first_above: Option<usize>,
+    merge: bool,
+    exports: Vec<String>,
+    slices: Vec<(export::Axis, isize)>,
+    slice_region: Option<Cuboid>,
+}
+
+fn parse_point(input: &str) -> Result<(isize, isize, isize), Box<dyn Error>> {
+    let coordinates = input
+        .split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<Vec<isize>, _>>()?;
+    match coordinates[..] {
+        [x, y, z] => Ok((x, y, z)),
+        _ => Err(Box::new(ParseError)),
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options {
+        engine: "subtract".to_string(),
+        queries: vec![],
+        inverted_ranges: InvertedRanges::Reject,
+        trace: false,
+        after: None,
+        first_above: None,
+        merge: false,
+        exports: vec![],
+        slices: vec![],
+        slice_region: None,
+    };
+    // Regions are parsed after all arguments, as `--normalize-ranges` may
+    // come after them.
+    let mut slice_region = None;
+    let mut queries = vec![];
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            options.engine = arg;
+            continue;
+        }
+        if arg == "--normalize-ranges" {
+            options.inverted_ranges = InvertedRanges::Normalize;
+            continue;
+        }
+        if arg == "--trace" {
+            options.trace = true;
+            continue;
+        }
+        if arg == "--merge" {
+            options.merge = true;
+            continue;
+        }
+        let value = args.next().ok_or(ParseError)?;
+        match arg.as_str() {
+            "--after" => {
+                options.after = Some(value.parse()?);
+                continue;
+            }
+            "--first-above" => {
+                options.first_above = Some(value.parse()?);
+                continue;
+            }
+            "--export" => {
+                export::Format::from_path(&value)?;
+                options.exports.push(value);
+                continue;
+            }
+            "--slice" => {
+                options.slices.push(export::parse_plane(&value)?);
+                continue;
+            }
+            "--slice-region" => {
+                slice_region = Some(value);
+                continue;
+            }
+            _ => {}
+        }
+        queries.push((arg, value));
+    }
+    let region = |value: &str| Cuboid::try_parse(value, options.inverted_ranges);
+    if let Some(value) = slice_region {
+        options.slice_region = Some(region(&value)?);
+    }
+    for (arg, value) in queries {
+        options.queries.push(match arg.as_str() {
+            "--count" => Query::Count(region(&value)?),
+            "--is-on" => Query::IsOn(parse_point(&value)?),
+            "--list" => Query::List(region(&value)?),
+            _ => return Err(Box::new(ParseError)),
+        });
+    }
+    Ok(options)
+}
+
+const CHECKPOINT_INTERVAL: usize = 100;
+
+fn run<E: Engine>(steps: &[RebootStep], options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut history = History::<E>::new(CHECKPOINT_INTERVAL);
+    for step in steps {
+        history.apply(step);
+    }
+    if options.trace {
+        for k in 1..=history.len() {
+            println!(
+                "After step {}: {} on",
+                k,
+                history.count_after(k).unwrap_or(0)
+            );
+        }
+    }
+    if let Some(threshold) = options.first_above {
+        match history.first_step_where(|count| count > threshold) {
+            Some(k) => println!("More than {} on after step {}", threshold, k),
+            None => println!("Never more than {} on", threshold),
+        }
+    }
+    if let Some(k) = options.after {
+        if !history.rollback(k) {
+            return Err(format!("there are only {} steps", history.len()).into());
+        }
+    }
+    let reactor = history.current();
+    println!("Part 1: {}", reactor.count_on_in(&INIT_REGION));
+    println!("Part 2: {}", reactor.count_on());
+    for query in options.queries.iter() {
+        match query {
+            Query::Count(region) => {
+                println!("{}: {} on", region, reactor.count_on_in(region))
+            }
+            Query::IsOn(p) => println!(
+                "{},{},{}: {}",
+                p.0,
+                p.1,
+                p.2,
+                if reactor.is_on(*p) { "on" } else { "off" }
+            ),
+            Query::List(region) => {
+                println!("{}:", region);
+                for cuboid in reactor.on_cuboids_in(region) {
+                    println!("  {}", cuboid);
+                }
+            }
         }
-        reactor_part2.apply(&step)
     }
-    Ok((reactor_part1.count_on(), reactor_part2.count_on()))
+
+    if options.exports.is_empty() && options.slices.is_empty() {
+        return Ok(());
+    }
+    let mut cuboids = reactor.on_cuboids();
+    if options.merge {
+        cuboids = export::merge(cuboids);
+    }
+    for path in options.exports.iter() {
+        let format = export::Format::from_path(path)?;
+        let mut out = BufWriter::new(File::create(path)?);
+        export::write(&cuboids, format, &mut out)?;
+    }
+    let stdout = io::stdout();
+    for &(axis, value) in options.slices.iter() {
+        export::render_slice(
+            &cuboids,
+            axis,
+            value,
+            options.slice_region.as_ref(),
+            &mut stdout.lock(),
+        )?;
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let options = parse_args(env::args().skip(1))?;
     let stdin = io::stdin();
-    let (part1, part2) = reboot_reactor(&mut stdin.lock())?;
-    println!("Part 1: {}", part1);
-    println!("Part 2: {}", part2);
-    Ok(())
+    let steps = parse_steps(&mut stdin.lock(), options.inverted_ranges)?;
+    match options.engine.as_str() {
+        "subtract" => run::<Reactor>(&steps, &options),
+        "signed" => run::<SignedReactor>(&steps, &options),
+        "compare" => {
+            let result = timed_reboot::<Reactor>("subtract", &steps);
+            if timed_reboot::<SignedReactor>("signed", &steps) != result {
+                return Err("engines disagree".into());
+            }
+            run::<SignedReactor>(&steps, &options)
+        }
+        _ => Err(Box::new(ParseError)),
+    }
 }
 
 #[cfg(test)]
@@ -211,15 +747,367 @@ mod tests {
     #[test]
     fn test_part1() {
         let mut buf: &[u8] = include_bytes!("../test.input");
-        assert_eq!(reboot_reactor(&mut buf).unwrap().0, 590784);
+        assert_eq!(
+            reboot::<Reactor>(&parse_steps(&mut buf, InvertedRanges::Reject).unwrap()).0,
+            590784
+        );
+        let mut buf: &[u8] = include_bytes!("../test.input");
+        assert_eq!(
+            reboot::<SignedReactor>(&parse_steps(&mut buf, InvertedRanges::Reject).unwrap()).0,
+            590784
+        );
     }
 
     #[test]
     fn test_part2() {
         let mut buf: &[u8] = include_bytes!("../test2.input");
         assert_eq!(
-            reboot_reactor(&mut buf).unwrap(),
+            reboot::<Reactor>(&parse_steps(&mut buf, InvertedRanges::Reject).unwrap()),
+            (474140, 2758514936282235)
+        );
+        let mut buf: &[u8] = include_bytes!("../test2.input");
+        assert_eq!(
+            reboot::<SignedReactor>(&parse_steps(&mut buf, InvertedRanges::Reject).unwrap()),
             (474140, 2758514936282235)
         );
     }
+
+    fn test_queries<E: Engine>() {
+        let mut buf: &[u8] = include_bytes!("../test.input");
+        let mut reactor = E::default();
+        for step in parse_steps(&mut buf, InvertedRanges::Reject)
+            .unwrap()
+            .iter()
+        {
+            reactor.apply(step);
+        }
+        assert_eq!(reactor.count_on_in(&INIT_REGION), 590784);
+        let on_cuboids = reactor.on_cuboids_in(&INIT_REGION);
+        assert_eq!(
+            on_cuboids.iter().map(|c| c.num_cubes()).sum::<usize>(),
+            590784
+        );
+        for (i, a) in on_cuboids.iter().enumerate() {
+            assert_eq!(a.intersect(&INIT_REGION).as_ref(), Some(a));
+            for b in on_cuboids[i + 1..].iter() {
+                assert_eq!(a.intersect(b), None);
+            }
+        }
+
+        // on x=-22..26,y=-27..20,z=-29..19 is never turned off
+        assert!(reactor.is_on((0, 0, 0)));
+        // off x=-48..-32,y=26..41,z=-47..-37
+        assert!(!reactor.is_on((-40, 30, -40)));
+        // outside of all steps
+        assert!(!reactor.is_on((100000, 0, 0)));
+        let region = Cuboid {
+            x_range: (-40, -30),
+            y_range: (30, 32),
+            z_range: (-40, -39),
+        };
+        assert_eq!(
+            reactor.count_on_in(&region),
+            (-40..-30)
+                .flat_map(|x| (30..32).map(move |y| (x, y, -40)))
+                .filter(|&p| reactor.is_on(p))
+                .count()
+        );
+    }
+
+    #[test]
+    fn test_queries_subtract() {
+        test_queries::<Reactor>();
+    }
+
+    #[test]
+    fn test_queries_signed() {
+        test_queries::<SignedReactor>();
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = [
+            "signed",
+            "--count",
+            "x=-5..5,y=0..0,z=1..2",
+            "--is-on",
+            "1,-2,3",
+            "--after",
+            "12",
+            "--trace",
+        ]
+        .iter()
+        .map(|arg| arg.to_string());
+        assert_eq!(
+            parse_args(args).unwrap(),
+            Options {
+                engine: "signed".to_string(),
+                queries: vec![
+                    Query::Count(Cuboid {
+                        x_range: (-5, 6),
+                        y_range: (0, 1),
+                        z_range: (1, 3)
+                    }),
+                    Query::IsOn((1, -2, 3))
+                ],
+                inverted_ranges: InvertedRanges::Reject,
+                trace: true,
+                after: Some(12),
+                first_above: None,
+                merge: false,
+                exports: vec![],
+                slices: vec![],
+                slice_region: None,
+            }
+        );
+        assert!(parse_args(["--is-on", "1,2"].iter().map(|arg| arg.to_string())).is_err());
+
+        // Regions are normalized wherever `--normalize-ranges` appears.
+        let region = "x=3..1,y=0..0,z=0..0";
+        let expected = Cuboid {
+            x_range: (1, 4),
+            y_range: (0, 1),
+            z_range: (0, 1),
+        };
+        for args in [
+            [
+                "--slice-region",
+                region,
+                "--list",
+                region,
+                "--normalize-ranges",
+            ],
+            [
+                "--normalize-ranges",
+                "--slice-region",
+                region,
+                "--list",
+                region,
+            ],
+        ] {
+            let options = parse_args(args.iter().map(|arg| arg.to_string())).unwrap();
+            assert_eq!(options.slice_region, Some(expected.clone()));
+            assert_eq!(options.queries, vec![Query::List(expected.clone())]);
+        }
+        assert!(parse_args(["--slice-region", region].iter().map(|arg| arg.to_string())).is_err());
+    }
+
+    #[test]
+    fn test_parse_steps() {
+        let mut buf: &[u8] = b"# reboot script
+on x=-20..26,y=-36..17,z=-47..7
+
+toggle x=10..5,y=0..0,z=-1..1 # inverted
+  off x=0..0, y=0..0, z=0..0
+";
+        let cuboid = |x_range, y_range, z_range| Cuboid {
+            x_range,
+            y_range,
+            z_range,
+        };
+        assert_eq!(
+            parse_steps(&mut buf, InvertedRanges::Normalize).unwrap(),
+            vec![
+                RebootStep {
+                    action: Action::On,
+                    cuboid: cuboid((-20, 27), (-36, 18), (-47, 8)),
+                },
+                RebootStep {
+                    action: Action::Toggle,
+                    cuboid: cuboid((5, 11), (0, 1), (-1, 2)),
+                },
+                RebootStep {
+                    action: Action::Off,
+                    cuboid: cuboid((0, 1), (0, 1), (0, 1)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |input: &str| {
+            let mut buf = input.as_bytes();
+            parse_steps(&mut buf, InvertedRanges::Reject)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            error("on x=1..2,y=1..2,z=1..2\n# comment\nof x=1..2,y=1..2,z=1..2"),
+            "line 3: action: expected 'on', 'off' or 'toggle', found 'of'"
+        );
+        assert_eq!(
+            error("on x=1..2,y=10..5,z=1..2"),
+            "line 1: y: inverted range 10..5"
+        );
+        assert_eq!(
+            error("on x=1..2,y=1..2,z=1.2"),
+            "line 1: z: expected 'start..end', found '1.2'"
+        );
+        assert_eq!(
+            error("off x=1..2,z=1..2,y=1..2"),
+            "line 1: y: expected 'y=', found 'z=1..2'"
+        );
+        assert_eq!(
+            error("on x=1..a,y=1..2,z=1..2"),
+            "line 1: x: invalid number 'a'"
+        );
+        assert_eq!(error("on x=1..2,y=1..2"), "line 1: z: missing");
+        assert_eq!(
+            error("on x=1..2,y=1..2,z=1..2,w=1..2"),
+            "line 1: cuboid: unexpected field 'w=1..2'"
+        );
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut buf: &[u8] = b"on x=0..1,y=0..1,z=0..1
+toggle x=1..2,y=1..2,z=1..2";
+        let steps = parse_steps(&mut buf, InvertedRanges::Reject).unwrap();
+        // 8 - 1 turned off + 7 turned on
+        assert_eq!(reboot::<Reactor>(&steps), (14, 14));
+        assert_eq!(reboot::<SignedReactor>(&steps), (14, 14));
+    }
+
+    #[test]
+    fn test_on_cuboids() {
+        let mut buf: &[u8] = include_bytes!("../test.input");
+        let steps = parse_steps(&mut buf, InvertedRanges::Reject).unwrap();
+        let mut reactor = Reactor::default();
+        let mut signed_reactor = SignedReactor::default();
+        for step in steps[..6].iter() {
+            reactor.apply(step);
+            signed_reactor.apply(step);
+        }
+        for on_cuboids in [reactor.on_cuboids(), signed_reactor.on_cuboids()] {
+            let merged = export::merge(on_cuboids.clone());
+            assert!(merged.len() <= on_cuboids.len());
+            for cuboids in [on_cuboids, merged] {
+                assert_eq!(
+                    cuboids.iter().map(|c| c.num_cubes()).sum::<usize>(),
+                    reactor.count_on()
+                );
+                for (i, a) in cuboids.iter().enumerate() {
+                    for b in cuboids[i + 1..].iter() {
+                        assert_eq!(a.intersect(b), None);
+                    }
+                }
+            }
+        }
+        assert!(SignedReactor::default().on_cuboids().is_empty());
+    }
+
+    #[test]
+    fn test_signed_on_cuboids() {
+        let mut buf: &[u8] = include_bytes!("../test2.input");
+        let steps = parse_steps(&mut buf, InvertedRanges::Reject).unwrap();
+        let mut reactor = Reactor::default();
+        let mut signed_reactor = SignedReactor::default();
+        for step in steps.iter() {
+            reactor.apply(step);
+            signed_reactor.apply(step);
+        }
+        let region = Cuboid {
+            x_range: (-20000, 20000),
+            y_range: (-20000, 20000),
+            z_range: (-20000, 20000),
+        };
+        for (on_cuboids, count) in [
+            (signed_reactor.on_cuboids(), reactor.count_on()),
+            (
+                signed_reactor.on_cuboids_in(&region),
+                reactor.count_on_in(&region),
+            ),
+        ] {
+            assert_eq!(
+                on_cuboids.iter().map(|c| c.num_cubes()).sum::<usize>(),
+                count
+            );
+            // Every cuboid is lit entirely, so together they cover exactly
+            // the lit cubes.
+            for cuboid in on_cuboids.iter() {
+                assert_eq!(reactor.count_on_in(cuboid), cuboid.num_cubes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_signed_state_independent_of_steps() {
+        let cuboid = |start: isize| Cuboid {
+            x_range: (start, start + 10),
+            y_range: (start, start + 10),
+            z_range: (0, 10),
+        };
+        let mut signed_reactor = SignedReactor::default();
+        for i in 0..3000 {
+            signed_reactor.apply(&RebootStep {
+                action: [Action::On, Action::Toggle, Action::Off, Action::Toggle][i % 4],
+                cuboid: cuboid(i as isize % 3 * 5),
+            });
+        }
+        // At most the three cuboids and their intersections.
+        assert!(signed_reactor.signed_cubes.len() <= 7);
+    }
+
+    #[test]
+    fn test_engines_agree() {
+        let mut seed: u64 = 22;
+        let mut next = |n: isize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            ((seed >> 33) % n as u64) as isize
+        };
+        let mut random_range = || {
+            let start = next(40) - 20;
+            (start, start + 1 + next(15))
+        };
+        let steps: Vec<RebootStep> = (0..200)
+            .map(|i| RebootStep {
+                action: match i % 4 {
+                    0 => Action::Off,
+                    1 => Action::Toggle,
+                    _ => Action::On,
+                },
+                cuboid: Cuboid {
+                    x_range: random_range(),
+                    y_range: random_range(),
+                    z_range: random_range(),
+                },
+            })
+            .collect();
+        let mut reactor = Reactor::default();
+        let mut signed_reactor = SignedReactor::default();
+        for step in steps.iter() {
+            reactor.apply(step);
+            signed_reactor.apply(step);
+            assert_eq!(signed_reactor.count_on(), reactor.count_on());
+        }
+    }
+
+    #[test]
+    fn test_intersect() {
+        let a = Cuboid {
+            x_range: (0, 4),
+            y_range: (0, 4),
+            z_range: (0, 4),
+        };
+        let b = Cuboid {
+            x_range: (2, 6),
+            y_range: (-1, 1),
+            z_range: (3, 4),
+        };
+        assert_eq!(
+            a.intersect(&b),
+            Some(Cuboid {
+                x_range: (2, 4),
+                y_range: (0, 1),
+                z_range: (3, 4),
+            })
+        );
+        let c = Cuboid {
+            x_range: (4, 6),
+            ..b
+        };
+        assert_eq!(a.intersect(&c), None);
+    }
 }
@@ -1,48 +1,35 @@
-use std::{collections::HashMap, fmt::Display};
-
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
-enum Amphipod {
-    Amber,
-    Bronze,
-    Copper,
-    Desert,
-}
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fmt::{Display, Write},
+    hash::Hash,
+};
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+struct Amphipod(u8);
 
 impl Amphipod {
-    fn target(&self) -> usize {
-        use Amphipod::*;
-        match self {
-            Amber => 0,
-            Bronze => 1,
-            Copper => 2,
-            Desert => 3,
-        }
-    }
+    const AMBER: Self = Self(0);
+    const BRONZE: Self = Self(1);
+    const COPPER: Self = Self(2);
+    const DESERT: Self = Self(3);
 
-    fn energy(&self) -> usize {
-        use Amphipod::*;
-        match self {
-            Amber => 1,
-            Bronze => 10,
-            Copper => 100,
-            Desert => 1000,
-        }
+    fn target(&self) -> usize {
+        self.0 as usize
     }
 }
 
 impl Display for Amphipod {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use Amphipod::*;
-        f.write_str(match self {
-            Amber => "A",
-            Bronze => "B",
-            Copper => "C",
-            Desert => "D",
-        })
+        // Kinds beyond `Z` are written as their index.
+        match b'A'.checked_add(self.0).filter(u8::is_ascii_uppercase) {
+            Some(letter) => f.write_char(letter as char),
+            None => write!(f, "{}", self.0),
+        }
     }
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 enum Location {
     Free,
     Occupied(Amphipod),
@@ -64,90 +51,405 @@ impl Display for Location {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
-struct State {
-    hallway: [Location; 11],
-    rooms: [Vec<Location>; 4],
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Burrow {
+    hallway_len: usize,
+    doors: Vec<usize>,
+    room_depth: usize,
+    energies: Vec<usize>,
+}
+
+impl Burrow {
+    fn new(hallway_len: usize, doors: Vec<usize>, room_depth: usize, energies: Vec<usize>) -> Self {
+        assert_eq!(
+            doors.len(),
+            energies.len(),
+            "each amphipod kind needs exactly one target room"
+        );
+        assert!(
+            doors.iter().all(|&door| door < hallway_len),
+            "doors must lead into the hallway"
+        );
+        assert!(
+            energies.len() <= u8::MAX as usize,
+            "too many amphipod kinds"
+        );
+        Self {
+            hallway_len,
+            doors,
+            room_depth,
+            energies,
+        }
+    }
+
+    fn standard(room_depth: usize) -> Self {
+        Self::new(11, vec![2, 4, 6, 8], room_depth, vec![1, 10, 100, 1000])
+    }
+
+    fn num_rooms(&self) -> usize {
+        self.doors.len()
+    }
+
+    fn is_door(&self, hallway_location: usize) -> bool {
+        self.doors.contains(&hallway_location)
+    }
+
+    fn room_entrance(&self, room_location: usize) -> usize {
+        self.doors[room_location]
+    }
+
+    fn energy(&self, amphipod: Amphipod) -> usize {
+        self.energies[amphipod.target()]
+    }
+
+    fn start_state(&self, rooms: Vec<Vec<Location>>) -> State {
+        assert_eq!(rooms.len(), self.num_rooms());
+        assert!(rooms.iter().all(|room| room.len() == self.room_depth));
+        State {
+            hallway: vec![Location::Free; self.hallway_len],
+            rooms,
+        }
+    }
+
+    fn display<S: BurrowState>(&self, state: &S) -> StateDisplay<'_> {
+        StateDisplay {
+            burrow: self,
+            state: state.unpack(self),
+        }
+    }
 }
 
-impl State {
-    fn is_target(&self) -> bool {
-        self.hallway.iter().all(|location| location.is_free())
-            && self.rooms.iter().enumerate().all(|(i, room)| {
-                room.iter().all(|location| match location {
+trait BurrowState: Clone + Hash + Eq + Ord {
+    fn hallway(&self, burrow: &Burrow, hallway_location: usize) -> Location;
+    fn room(&self, burrow: &Burrow, room_location: usize, room_index: usize) -> Location;
+    fn swap(
+        &mut self,
+        burrow: &Burrow,
+        hallway_location: usize,
+        room_location: usize,
+        room_index: usize,
+    );
+    fn unpack(&self, burrow: &Burrow) -> State;
+
+    // Bytes allocated outside of the state itself.
+    #[cfg(test)]
+    fn heap_size(&self) -> usize {
+        0
+    }
+
+    fn room_iter<'a>(
+        &'a self,
+        burrow: &'a Burrow,
+        room_location: usize,
+    ) -> impl Iterator<Item = Location> + 'a {
+        (0..burrow.room_depth).map(move |room_index| self.room(burrow, room_location, room_index))
+    }
+
+    fn is_target(&self, burrow: &Burrow) -> bool {
+        (0..burrow.hallway_len).all(|i| self.hallway(burrow, i).is_free())
+            && (0..burrow.num_rooms()).all(|i| {
+                self.room_iter(burrow, i).all(|location| match location {
                     Location::Free => false,
                     Location::Occupied(amphipod) => amphipod.target() == i,
                 })
             })
     }
+
+    // Each misplaced amphipod has to at least walk to its room's entrance and in.
+    fn min_remaining_energy(&self, burrow: &Burrow) -> usize {
+        let from_hallway: usize = (0..burrow.hallway_len)
+            .filter_map(
+                |hallway_location| match self.hallway(burrow, hallway_location) {
+                    Location::Free => None,
+                    Location::Occupied(amphipod) => Some(
+                        (hallway_location.abs_diff(burrow.room_entrance(amphipod.target())) + 1)
+                            * burrow.energy(amphipod),
+                    ),
+                },
+            )
+            .sum();
+        let from_rooms: usize = (0..burrow.num_rooms())
+            .flat_map(|room_location| {
+                self.room_iter(burrow, room_location)
+                    .enumerate()
+                    .filter_map(move |(room_index, location)| match location {
+                        Location::Free => None,
+                        Location::Occupied(amphipod) if amphipod.target() != room_location => Some(
+                            (room_index
+                                + 1
+                                + burrow
+                                    .room_entrance(room_location)
+                                    .abs_diff(burrow.room_entrance(amphipod.target()))
+                                + 1)
+                                * burrow.energy(amphipod),
+                        ),
+                        Location::Occupied(amphipod) => {
+                            let is_blocking = self
+                                .room_iter(burrow, room_location)
+                                .skip(room_index + 1)
+                                .any(|l| match l {
+                                    Location::Occupied(a) => a.target() != room_location,
+                                    Location::Free => false,
+                                });
+                            if is_blocking {
+                                Some((room_index + 1 + 2 + 1) * burrow.energy(amphipod))
+                            } else {
+                                None
+                            }
+                        }
+                    })
+            })
+            .sum();
+        from_hallway + from_rooms
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+struct State {
+    hallway: Vec<Location>,
+    rooms: Vec<Vec<Location>>,
+}
+
+impl BurrowState for State {
+    fn hallway(&self, _burrow: &Burrow, hallway_location: usize) -> Location {
+        self.hallway[hallway_location]
+    }
+
+    fn room(&self, _burrow: &Burrow, room_location: usize, room_index: usize) -> Location {
+        self.rooms[room_location][room_index]
+    }
+
+    fn swap(
+        &mut self,
+        _burrow: &Burrow,
+        hallway_location: usize,
+        room_location: usize,
+        room_index: usize,
+    ) {
+        std::mem::swap(
+            &mut self.hallway[hallway_location],
+            &mut self.rooms[room_location][room_index],
+        );
+    }
+
+    fn unpack(&self, _burrow: &Burrow) -> State {
+        self.clone()
+    }
+
+    #[cfg(test)]
+    fn heap_size(&self) -> usize {
+        let location = std::mem::size_of::<Location>();
+        (self.hallway.capacity() + self.rooms.iter().map(|room| room.capacity()).sum::<usize>())
+            * location
+            + self.rooms.capacity() * std::mem::size_of::<Vec<Location>>()
+    }
 }
 
-impl Display for State {
+const BITS_PER_CELL: usize = 3;
+const CELL_MASK: u128 = (1 << BITS_PER_CELL) - 1;
+
+// Stores the hallway followed by the rooms with 3 bits per cell, where 0 is a
+// free cell and any other value is the amphipod kind plus one.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+struct PackedState(u128);
+
+impl PackedState {
+    fn can_pack(burrow: &Burrow) -> bool {
+        let num_cells = burrow.hallway_len + burrow.num_rooms() * burrow.room_depth;
+        num_cells * BITS_PER_CELL <= u128::BITS as usize
+            && burrow.energies.len() < (1 << BITS_PER_CELL)
+    }
+
+    fn pack(burrow: &Burrow, state: &State) -> Option<Self> {
+        if !Self::can_pack(burrow) {
+            return None;
+        }
+        let mut packed = Self(0);
+        for (hallway_location, &location) in state.hallway.iter().enumerate() {
+            packed.set_cell(hallway_location, location);
+        }
+        for (room_location, room) in state.rooms.iter().enumerate() {
+            for (room_index, &location) in room.iter().enumerate() {
+                packed.set_cell(Self::room_cell(burrow, room_location, room_index), location);
+            }
+        }
+        Some(packed)
+    }
+
+    fn room_cell(burrow: &Burrow, room_location: usize, room_index: usize) -> usize {
+        burrow.hallway_len + room_location * burrow.room_depth + room_index
+    }
+
+    fn cell(&self, cell: usize) -> Location {
+        match (self.0 >> (cell * BITS_PER_CELL)) & CELL_MASK {
+            0 => Location::Free,
+            value => Location::Occupied(Amphipod(value as u8 - 1)),
+        }
+    }
+
+    fn set_cell(&mut self, cell: usize, location: Location) {
+        let value = match location {
+            Location::Free => 0,
+            Location::Occupied(amphipod) => amphipod.0 as u128 + 1,
+        };
+        let shift = cell * BITS_PER_CELL;
+        self.0 = (self.0 & !(CELL_MASK << shift)) | (value << shift);
+    }
+}
+
+impl BurrowState for PackedState {
+    fn hallway(&self, _burrow: &Burrow, hallway_location: usize) -> Location {
+        self.cell(hallway_location)
+    }
+
+    fn room(&self, burrow: &Burrow, room_location: usize, room_index: usize) -> Location {
+        self.cell(Self::room_cell(burrow, room_location, room_index))
+    }
+
+    fn swap(
+        &mut self,
+        burrow: &Burrow,
+        hallway_location: usize,
+        room_location: usize,
+        room_index: usize,
+    ) {
+        let room_cell = Self::room_cell(burrow, room_location, room_index);
+        let hallway = self.cell(hallway_location);
+        let room = self.cell(room_cell);
+        self.set_cell(hallway_location, room);
+        self.set_cell(room_cell, hallway);
+    }
+
+    fn unpack(&self, burrow: &Burrow) -> State {
+        State {
+            hallway: (0..burrow.hallway_len)
+                .map(|hallway_location| self.hallway(burrow, hallway_location))
+                .collect(),
+            rooms: (0..burrow.num_rooms())
+                .map(|room_location| self.room_iter(burrow, room_location).collect())
+                .collect(),
+        }
+    }
+}
+
+struct StateDisplay<'a> {
+    burrow: &'a Burrow,
+    state: State,
+}
+
+impl<'a> Display for StateDisplay<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("#############\n#")?;
-        for location in self.hallway {
+        let width = self.burrow.hallway_len + 2;
+        f.write_str(&"#".repeat(width))?;
+        f.write_str("\n#")?;
+        for location in &self.state.hallway {
             location.fmt(f)?;
         }
         f.write_str("#\n")?;
 
-        let room_len = self.rooms.iter().map(|room| room.len()).min().unwrap_or(0);
-        for i in 0..room_len {
-            if i == 0 {
-                f.write_str("##")?;
-            } else {
-                f.write_str("  ")?;
-            }
-            f.write_fmt(format_args!(
-                "#{}#{}#{}#{}#",
-                self.rooms[0][i], self.rooms[1][i], self.rooms[2][i], self.rooms[3][i]
-            ))?;
-            if i == 0 {
-                f.write_str("##")?;
+        let first_wall = self.burrow.doors.iter().min().copied().unwrap_or(0);
+        let last_wall = self.burrow.doors.iter().max().map_or(0, |door| door + 2);
+        for i in 0..self.burrow.room_depth {
+            for column in 0..width {
+                if let Some(room_location) = self
+                    .burrow
+                    .doors
+                    .iter()
+                    .position(|&door| door + 1 == column)
+                {
+                    self.state.rooms[room_location][i].fmt(f)?;
+                } else if i == 0 || (first_wall..=last_wall).contains(&column) {
+                    f.write_char('#')?;
+                } else if column < last_wall {
+                    f.write_char(' ')?;
+                }
             }
             f.write_str("\n")?;
         }
-        f.write_str("  #########\n")
+        f.write_str(&" ".repeat(first_wall))?;
+        f.write_str(&"#".repeat(last_wall + 1 - first_wall))?;
+        f.write_str("\n")
     }
 }
 
-struct Solver {
-    memo_table: HashMap<State, Option<usize>>,
+struct Solver<'a, S: BurrowState> {
+    burrow: &'a Burrow,
+    best_costs: HashMap<S, usize>,
+    queue: BinaryHeap<Reverse<(usize, usize, S)>>,
 }
 
-impl Solver {
-    fn new() -> Self {
+impl<'a, S: BurrowState> Solver<'a, S> {
+    fn new(burrow: &'a Burrow) -> Self {
         Self {
-            memo_table: HashMap::new(),
+            burrow,
+            best_costs: HashMap::new(),
+            queue: BinaryHeap::new(),
         }
     }
 
-    fn solve(&mut self, state: &State) -> Option<usize> {
-        if let Some(&result) = self.memo_table.get(state) {
-            return result;
+    fn push(&mut self, energy: usize, state: S) {
+        if self
+            .best_costs
+            .get(&state)
+            .is_none_or(|&best| energy < best)
+        {
+            self.best_costs.insert(state.clone(), energy);
+            self.queue.push(Reverse((
+                energy + state.min_remaining_energy(self.burrow),
+                energy,
+                state,
+            )));
         }
+    }
+
+    fn solve(&mut self, start_state: S) -> Option<usize> {
+        self.push(0, start_state);
 
-        if state.is_target() {
-            return Some(0);
+        while let Some(Reverse((_, energy, state))) = self.queue.pop() {
+            if self
+                .best_costs
+                .get(&state)
+                .is_some_and(|&best| energy > best)
+            {
+                continue;
+            }
+            if state.is_target(self.burrow) {
+                return Some(energy);
+            }
+            for (move_energy, next_state) in NextStateIterator::new(self.burrow, &state) {
+                self.push(energy + move_energy, next_state);
+            }
         }
 
-        let result = NextStateIterator::new(state)
-            .filter_map(|(energy, next_state)| {
-                self.solve(&next_state)
-                    .map(|sub_energy| energy + sub_energy)
-            })
-            .min();
-        self.memo_table.insert(state.clone(), result);
-        result
+        None
+    }
+
+    // Approximate bytes held by the visited states and the queue.
+    #[cfg(test)]
+    fn memory(&self) -> usize {
+        let states = self.best_costs.keys().map(S::heap_size).sum::<usize>()
+            + self
+                .queue
+                .iter()
+                .map(|Reverse((_, _, state))| state.heap_size())
+                .sum::<usize>();
+        self.best_costs.capacity() * std::mem::size_of::<(S, usize)>()
+            + self.queue.capacity() * std::mem::size_of::<(usize, usize, S)>()
+            + states
     }
 }
 
-fn solve(start_state: &State) -> Option<usize> {
-    Solver::new().solve(start_state)
+fn solve(burrow: &Burrow, start_state: &State) -> Option<usize> {
+    match PackedState::pack(burrow, start_state) {
+        Some(packed) => Solver::new(burrow).solve(packed),
+        None => Solver::new(burrow).solve(start_state.clone()),
+    }
 }
 
 #[derive(Debug)]
-struct NextStateIterator<'a> {
-    state: &'a State,
+struct NextStateIterator<'a, S: BurrowState> {
+    burrow: &'a Burrow,
+    state: &'a S,
     hallway_location: usize,
     room_location: usize,
     room_index: usize,
@@ -155,9 +457,10 @@ struct NextStateIterator<'a> {
     is_exhausted: bool,
 }
 
-impl<'a> NextStateIterator<'a> {
-    fn new(state: &'a State) -> Self {
+impl<'a, S: BurrowState> NextStateIterator<'a, S> {
+    fn new(burrow: &'a Burrow, state: &'a S) -> Self {
         Self {
+            burrow,
             state,
             hallway_location: 0,
             room_location: 0,
@@ -174,43 +477,44 @@ impl<'a> NextStateIterator<'a> {
         room_index: usize,
         move_into_room: bool,
     ) -> bool {
-        let is_hallway_location_valid = hallway_location != 2
-            && hallway_location != 4
-            && hallway_location != 6
-            && hallway_location != 8;
+        let is_hallway_location_valid = !self.burrow.is_door(hallway_location);
         let are_start_and_end_valid = match (
             move_into_room,
-            self.state.hallway[hallway_location],
-            self.state.rooms[room_location][room_index],
+            self.state.hallway(self.burrow, hallway_location),
+            self.state.room(self.burrow, room_location, room_index),
         ) {
             (true, Location::Occupied(amphipod), Location::Free) => {
                 amphipod.target() == room_location
-                    && self.state.rooms[room_location]
-                        .iter()
+                    && self
+                        .state
+                        .room_iter(self.burrow, room_location)
                         .skip(room_index + 1)
-                        .all(|&l| l == Location::Occupied(amphipod))
+                        .all(|l| l == Location::Occupied(amphipod))
             }
-            (true, Location::Free, Location::Occupied(_)) => {
-                self.state.rooms[room_location].iter().any(|l| match l {
+            (true, Location::Free, Location::Occupied(_)) => self
+                .state
+                .room_iter(self.burrow, room_location)
+                .any(|l| match l {
                     Location::Occupied(a) => a.target() != room_location,
                     _ => false,
-                })
-            }
+                }),
             _ => false,
         };
-        let is_path_within_room_free = self.state.rooms[room_location]
-            .iter()
+        let is_path_within_room_free = self
+            .state
+            .room_iter(self.burrow, room_location)
             .take(room_index)
             .all(|l| l.is_free());
-        let mut hallway_range = if hallway_location < 2 * room_location + 2 {
-            (hallway_location + 1)..(2 * room_location + 2)
+        let room_entrance = self.burrow.room_entrance(room_location);
+        let mut hallway_range = if hallway_location < room_entrance {
+            (hallway_location + 1)..room_entrance
         } else {
-            (2 * room_location + 2 + 1)..hallway_location
+            (room_entrance + 1)..hallway_location
         };
         is_hallway_location_valid
             && are_start_and_end_valid
             && is_path_within_room_free
-            && hallway_range.all(|i| self.state.hallway[i].is_free())
+            && hallway_range.all(|i| self.state.hallway(self.burrow, i).is_free())
     }
 
     fn num_move_steps(
@@ -219,23 +523,24 @@ impl<'a> NextStateIterator<'a> {
         room_location: usize,
         room_index: usize,
     ) -> usize {
-        let hallway_range = if hallway_location < 2 * room_location + 2 {
-            hallway_location..(2 * room_location + 2)
+        let room_entrance = self.burrow.room_entrance(room_location);
+        let hallway_range = if hallway_location < room_entrance {
+            hallway_location..room_entrance
         } else {
-            (2 * room_location + 2)..(hallway_location)
+            room_entrance..hallway_location
         };
         hallway_range.count() + room_index + 1
     }
 
     fn increment(&mut self) {
         self.hallway_location += 1;
-        if self.hallway_location >= self.state.hallway.len() {
+        if self.hallway_location >= self.burrow.hallway_len {
             self.hallway_location = 0;
             self.room_location += 1;
-            if self.room_location >= self.state.rooms.len() {
+            if self.room_location >= self.burrow.num_rooms() {
                 self.room_location = 0;
                 self.room_index += 1;
-                if self.room_index >= self.state.rooms[0].len() {
+                if self.room_index >= self.burrow.room_depth {
                     self.room_index = 0;
                     if self.move_into_room {
                         self.is_exhausted = true;
@@ -261,8 +566,8 @@ impl<'a> NextStateIterator<'a> {
     }
 }
 
-impl<'a> Iterator for NextStateIterator<'a> {
-    type Item = (usize, State);
+impl<'a, S: BurrowState> Iterator for NextStateIterator<'a, S> {
+    type Item = (usize, S);
 
     fn next(&mut self) -> Option<Self::Item> {
         self.increment_until_valid();
@@ -271,16 +576,19 @@ impl<'a> Iterator for NextStateIterator<'a> {
         }
 
         let mut next_state = self.state.clone();
-        next_state.rooms[self.room_location][self.room_index] =
-            self.state.hallway[self.hallway_location];
-        next_state.hallway[self.hallway_location] =
-            self.state.rooms[self.room_location][self.room_index];
+        next_state.swap(
+            self.burrow,
+            self.hallway_location,
+            self.room_location,
+            self.room_index,
+        );
         let energy = match (
-            self.state.hallway[self.hallway_location],
-            self.state.rooms[self.room_location][self.room_index],
+            self.state.hallway(self.burrow, self.hallway_location),
+            self.state
+                .room(self.burrow, self.room_location, self.room_index),
         ) {
-            (Location::Occupied(amphipod), Location::Free) => amphipod.energy(),
-            (Location::Free, Location::Occupied(amphipod)) => amphipod.energy(),
+            (Location::Occupied(amphipod), Location::Free) => self.burrow.energy(amphipod),
+            (Location::Free, Location::Occupied(amphipod)) => self.burrow.energy(amphipod),
             _ => unreachable!(),
         };
 
@@ -297,95 +605,139 @@ impl<'a> Iterator for NextStateIterator<'a> {
 }
 
 fn main() {
-    println!(
-        "Part 1: {}",
-        solve(&State {
-            hallway: [Location::Free; 11],
-            rooms: [
-                vec![
-                    Location::Occupied(Amphipod::Amber),
-                    Location::Occupied(Amphipod::Desert)
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Amber)
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Desert)
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Bronze)
-                ],
-            ]
-        })
-        .unwrap()
-    );
-    println!(
-        "Part 2: {}",
-        solve(&State {
-            hallway: [Location::Free; 11],
-            rooms: [
-                vec![
-                    Location::Occupied(Amphipod::Amber),
-                    Location::Occupied(Amphipod::Desert),
-                    Location::Occupied(Amphipod::Desert),
-                    Location::Occupied(Amphipod::Desert)
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Amber)
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Amber),
-                    Location::Occupied(Amphipod::Desert)
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Amber),
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Bronze)
-                ],
-            ]
-        })
-        .unwrap()
-    );
+    // Prints the starting states back out in the input format.
+    let show = std::env::args().skip(1).any(|arg| arg == "--show");
+
+    let burrow = Burrow::standard(2);
+    let part1 = burrow.start_state(vec![
+        vec![
+            Location::Occupied(Amphipod::AMBER),
+            Location::Occupied(Amphipod::DESERT),
+        ],
+        vec![
+            Location::Occupied(Amphipod::COPPER),
+            Location::Occupied(Amphipod::AMBER),
+        ],
+        vec![
+            Location::Occupied(Amphipod::BRONZE),
+            Location::Occupied(Amphipod::DESERT),
+        ],
+        vec![
+            Location::Occupied(Amphipod::COPPER),
+            Location::Occupied(Amphipod::BRONZE),
+        ],
+    ]);
+
+    if show {
+        print!("{}", burrow.display(&part1));
+    }
+    println!("Part 1: {}", solve(&burrow, &part1).unwrap());
+
+    let burrow = Burrow::standard(4);
+    let part2 = burrow.start_state(vec![
+        vec![
+            Location::Occupied(Amphipod::AMBER),
+            Location::Occupied(Amphipod::DESERT),
+            Location::Occupied(Amphipod::DESERT),
+            Location::Occupied(Amphipod::DESERT),
+        ],
+        vec![
+            Location::Occupied(Amphipod::COPPER),
+            Location::Occupied(Amphipod::COPPER),
+            Location::Occupied(Amphipod::BRONZE),
+            Location::Occupied(Amphipod::AMBER),
+        ],
+        vec![
+            Location::Occupied(Amphipod::BRONZE),
+            Location::Occupied(Amphipod::BRONZE),
+            Location::Occupied(Amphipod::AMBER),
+            Location::Occupied(Amphipod::DESERT),
+        ],
+        vec![
+            Location::Occupied(Amphipod::COPPER),
+            Location::Occupied(Amphipod::AMBER),
+            Location::Occupied(Amphipod::COPPER),
+            Location::Occupied(Amphipod::BRONZE),
+        ],
+    ]);
+    if show {
+        print!("{}", burrow.display(&part2));
+    }
+    println!("Part 2: {}", solve(&burrow, &part2).unwrap());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::{Duration, Instant};
+
+    // The fastest of a few runs, with the solver's memory after the last one.
+    fn measure<S: BurrowState>(burrow: &Burrow, state: S) -> (Option<usize>, Duration, usize) {
+        let mut best = Duration::MAX;
+        let mut result = None;
+        let mut memory = 0;
+        for _ in 0..5 {
+            let mut solver = Solver::new(burrow);
+            let start = Instant::now();
+            result = solver.solve(state.clone());
+            best = best.min(start.elapsed());
+            memory = solver.memory();
+        }
+        (result, best, memory)
+    }
+
+    fn part2_test_scenario(burrow: &Burrow) -> State {
+        burrow.start_state(vec![
+            vec![
+                Location::Occupied(Amphipod::BRONZE),
+                Location::Occupied(Amphipod::DESERT),
+                Location::Occupied(Amphipod::DESERT),
+                Location::Occupied(Amphipod::AMBER),
+            ],
+            vec![
+                Location::Occupied(Amphipod::COPPER),
+                Location::Occupied(Amphipod::COPPER),
+                Location::Occupied(Amphipod::BRONZE),
+                Location::Occupied(Amphipod::DESERT),
+            ],
+            vec![
+                Location::Occupied(Amphipod::BRONZE),
+                Location::Occupied(Amphipod::BRONZE),
+                Location::Occupied(Amphipod::AMBER),
+                Location::Occupied(Amphipod::COPPER),
+            ],
+            vec![
+                Location::Occupied(Amphipod::DESERT),
+                Location::Occupied(Amphipod::AMBER),
+                Location::Occupied(Amphipod::COPPER),
+                Location::Occupied(Amphipod::AMBER),
+            ],
+        ])
+    }
 
     #[test]
     fn test_part1() {
-        let test_scenario = State {
-            hallway: [Location::Free; 11],
-            rooms: [
-                vec![
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Amber),
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Desert),
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Copper),
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Desert),
-                    Location::Occupied(Amphipod::Amber),
-                ],
+        let burrow = Burrow::standard(2);
+        let test_scenario = burrow.start_state(vec![
+            vec![
+                Location::Occupied(Amphipod::BRONZE),
+                Location::Occupied(Amphipod::AMBER),
             ],
-        };
+            vec![
+                Location::Occupied(Amphipod::COPPER),
+                Location::Occupied(Amphipod::DESERT),
+            ],
+            vec![
+                Location::Occupied(Amphipod::BRONZE),
+                Location::Occupied(Amphipod::COPPER),
+            ],
+            vec![
+                Location::Occupied(Amphipod::DESERT),
+                Location::Occupied(Amphipod::AMBER),
+            ],
+        ]);
         assert_eq!(
-            test_scenario.to_string(),
+            burrow.display(&test_scenario).to_string(),
             "\
 #############
 #...........#
@@ -394,42 +746,16 @@ mod tests {
   #########
 "
         );
-        assert_eq!(solve(&test_scenario), Some(12521));
+        assert_eq!(solve(&burrow, &test_scenario), Some(12521));
+        assert!(test_scenario.min_remaining_energy(&burrow) <= 12521);
     }
 
     #[test]
     fn test_part2() {
-        let test_scenario = State {
-            hallway: [Location::Free; 11],
-            rooms: [
-                vec![
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Desert),
-                    Location::Occupied(Amphipod::Desert),
-                    Location::Occupied(Amphipod::Amber),
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Desert),
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Bronze),
-                    Location::Occupied(Amphipod::Amber),
-                    Location::Occupied(Amphipod::Copper),
-                ],
-                vec![
-                    Location::Occupied(Amphipod::Desert),
-                    Location::Occupied(Amphipod::Amber),
-                    Location::Occupied(Amphipod::Copper),
-                    Location::Occupied(Amphipod::Amber),
-                ],
-            ],
-        };
+        let burrow = Burrow::standard(4);
+        let test_scenario = part2_test_scenario(&burrow);
         assert_eq!(
-            test_scenario.to_string(),
+            burrow.display(&test_scenario).to_string(),
             "\
 #############
 #...........#
@@ -440,6 +766,81 @@ mod tests {
   #########
 "
         );
-        assert_eq!(solve(&test_scenario), Some(44169));
+        assert_eq!(solve(&burrow, &test_scenario), Some(44169));
+        assert!(test_scenario.min_remaining_energy(&burrow) <= 44169);
+    }
+
+    #[test]
+    fn test_custom_burrow() {
+        let burrow = Burrow::new(7, vec![2, 4], 1, vec![1, 10]);
+        let test_scenario = burrow.start_state(vec![
+            vec![Location::Occupied(Amphipod::BRONZE)],
+            vec![Location::Occupied(Amphipod::AMBER)],
+        ]);
+        assert_eq!(
+            burrow.display(&test_scenario).to_string(),
+            "\
+#########
+#.......#
+###B#A###
+  #####
+"
+        );
+        assert_eq!(solve(&burrow, &test_scenario), Some(46));
+    }
+
+    #[test]
+    fn test_display_amphipod() {
+        assert_eq!(Amphipod::DESERT.to_string(), "D");
+        assert_eq!(Amphipod(25).to_string(), "Z");
+        assert_eq!(Amphipod(26).to_string(), "26");
+        assert_eq!(Amphipod(255).to_string(), "255");
+    }
+
+    #[test]
+    fn test_packed_state_round_trip() {
+        let burrow = Burrow::standard(4);
+        let mut state = part2_test_scenario(&burrow);
+        state.swap(&burrow, 0, 3, 0);
+        let packed = PackedState::pack(&burrow, &state).unwrap();
+        assert_eq!(packed.unpack(&burrow), state);
+        assert_eq!(
+            burrow.display(&packed).to_string(),
+            burrow.display(&state).to_string()
+        );
+    }
+
+    #[test]
+    fn test_packed_state_requires_small_burrow() {
+        let burrow = Burrow::new(11, vec![2, 4, 6, 8], 8, vec![1, 10, 100, 1000]);
+        let state = burrow.start_state(vec![vec![Location::Free; 8]; 4]);
+        assert_eq!(PackedState::pack(&burrow, &state), None);
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture bench`.
+    #[test]
+    #[ignore]
+    fn bench_part2_state_encodings() {
+        let burrow = Burrow::standard(4);
+        let state = part2_test_scenario(&burrow);
+        let packed = PackedState::pack(&burrow, &state).unwrap();
+
+        let (vec_result, vec_time, vec_memory) = measure(&burrow, state);
+        let (packed_result, packed_time, packed_memory) = measure(&burrow, packed);
+
+        println!("Vec-based state: {:?}, {} bytes", vec_time, vec_memory);
+        println!(
+            "Packed state:    {:?}, {} bytes",
+            packed_time, packed_memory
+        );
+        println!(
+            "Speedup: {:.1}x, memory: {:.1}x smaller",
+            vec_time.as_secs_f64() / packed_time.as_secs_f64(),
+            vec_memory as f64 / packed_memory as f64
+        );
+        assert_eq!(vec_result, Some(44169));
+        assert_eq!(packed_result, Some(44169));
+        assert!(packed_time < vec_time);
+        assert!(packed_memory < vec_memory);
     }
 }